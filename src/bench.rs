@@ -0,0 +1,62 @@
+use std::hint::black_box;
+use std::time::Instant;
+
+use rand::{thread_rng, Rng};
+
+use crate::camera::Project;
+use crate::ray::Ray;
+use crate::render::RenderSettings;
+use crate::shapes::Hit;
+use crate::shapes::packet::PacketList;
+use crate::simd::{BACKEND, LANES};
+use crate::vec3::Vec3;
+
+// Best of this many timed passes over the rays
+const ROUNDS: u32 = 5;
+
+// Times closest-hit queries against the scene, first as the plain object list and then with its
+// spheres in packets. The rays are one camera ray per pixel plus a diffuse bounce from wherever
+// each one lands, so both coherent and scattered rays are measured.
+pub fn run(scene: &PacketList, cam: &dyn Project, settings: &RenderSettings) {
+    let mut rng = thread_rng();
+    let mut rays = Vec::new();
+    for j in 0..settings.height {
+        for i in 0..settings.width {
+            let s = (i as f64 + rng.gen::<f64>()) / ((settings.width-1) as f64);
+            let t = (j as f64 + rng.gen::<f64>()) / ((settings.height-1) as f64);
+            let r = cam.get_ray(s, t);
+            if let Some(rec) = scene.hit(&r, 0.0001, f64::INFINITY) {
+                rays.push(Ray::new(rec.get_p(), rec.get_normal() + Vec3::random_unit_vector()));
+            }
+            rays.push(r);
+        }
+    }
+
+    // Both must agree on what every ray hits before their speeds mean anything
+    let mismatches = rays.iter()
+                         .filter(|r| {
+                             let a = scene.objects().hit(r, 0.0001, f64::INFINITY);
+                             let b = scene.hit(r, 0.0001, f64::INFINITY);
+                             a.map(|rec| (rec.get_object_id(), rec.get_t())) != b.map(|rec| (rec.get_object_id(), rec.get_t()))
+                         })
+                         .count();
+
+    println!("{} rays against {} objects, {} lanes of {}", rays.len(), scene.objects().len(), LANES, BACKEND);
+    let list = time("Object list", scene.objects(), &rays);
+    let packets = time("Sphere packets", scene, &rays);
+    println!("Speed-up: {:.2}x, {} differing hits", list / packets, mismatches);
+}
+
+// Seconds for the fastest pass
+fn time(name: &str, world: &dyn Hit, rays: &[Ray]) -> f64 {
+    let best = (0..ROUNDS).map(|_| {
+                              let start = Instant::now();
+                              for r in rays {
+                                  black_box(world.hit(r, 0.0001, f64::INFINITY));
+                              }
+                              start.elapsed().as_secs_f64()
+                          })
+                          .fold(f64::INFINITY, f64::min);
+    println!("{:<16}{:>8.2} Mrays/s", name, rays.len() as f64 / best / 1e6);
+    best
+}
\ No newline at end of file
@@ -0,0 +1,86 @@
+use std::f64::consts::PI;
+
+use crate::vec3::{Point3, Vec3};
+
+// Camera parameters at one moment of an animation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraKey {
+    pub time: f64,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub v_fov: f64,
+    pub focus_dist: f64,
+    pub aperture: f64,
+}
+
+// Keyframed camera. Every parameter follows a Catmull-Rom spline through the keys, so motion
+// passes through each key smoothly; before the first and after the last key the camera holds
+// still.
+pub struct CameraPath {
+    keys: Vec<CameraKey>,
+}
+
+impl CameraPath {
+    pub fn new(mut keys: Vec<CameraKey>) -> CameraPath {
+        assert!(!keys.is_empty(), "A camera path needs at least one key");
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        CameraPath { keys }
+    }
+
+    // Circles `look_at` once between times 0 and 1, starting from `start` and keeping its height
+    pub fn orbit(start: CameraKey, keys: u32) -> CameraPath {
+        let offset = start.look_from - start.look_at;
+        let keys = keys.max(4);
+        let orbit = (0..=keys).map(|i| {
+            let angle = 2.0 * PI * i as f64 / keys as f64;
+            let (sin, cos) = angle.sin_cos();
+            let rotated = Vec3::new(cos * offset.x() + sin * offset.z(), offset.y(), -sin * offset.x() + cos * offset.z());
+            CameraKey { time: i as f64 / keys as f64, look_from: start.look_at + rotated, ..start }
+        });
+        CameraPath::new(orbit.collect())
+    }
+
+    pub fn at(&self, time: f64) -> CameraKey {
+        let last = self.keys.len() - 1;
+        let next = self.keys.partition_point(|key| key.time <= time);
+        if next == 0 {
+            return CameraKey { time, ..self.keys[0] }
+        }
+        if next > last {
+            return CameraKey { time, ..self.keys[last] }
+        }
+
+        let (k1, k2) = (&self.keys[next - 1], &self.keys[next]);
+        // The end keys are repeated to give the spline its outer control points
+        let k0 = &self.keys[next.saturating_sub(2)];
+        let k3 = &self.keys[(next + 1).min(last)];
+        let u = (time - k1.time) / (k2.time - k1.time);
+
+        let point = |f: fn(&CameraKey) -> Point3| {
+            let [p0, p1, p2, p3] = [k0, k1, k2, k3].map(f);
+            Point3::new(catmull_rom(u, p0.x(), p1.x(), p2.x(), p3.x()),
+                        catmull_rom(u, p0.y(), p1.y(), p2.y(), p3.y()),
+                        catmull_rom(u, p0.z(), p1.z(), p2.z(), p3.z()))
+        };
+        let scalar = |f: fn(&CameraKey) -> f64| {
+            let [a0, a1, a2, a3] = [k0, k1, k2, k3].map(f);
+            catmull_rom(u, a0, a1, a2, a3)
+        };
+
+        CameraKey {
+            time,
+            look_from: point(|k| k.look_from),
+            look_at: point(|k| k.look_at),
+            v_fov: scalar(|k| k.v_fov),
+            focus_dist: scalar(|k| k.focus_dist).max(1e-3),
+            aperture: scalar(|k| k.aperture).max(0.0),
+        }
+    }
+}
+
+// Uniform Catmull-Rom spline between p1 (u = 0) and p2 (u = 1)
+fn catmull_rom(u: f64, p0: f64, p1: f64, p2: f64, p3: f64) -> f64 {
+    let u2 = u * u;
+    let u3 = u2 * u;
+    0.5 * (2.0 * p1 + (p2 - p0) * u + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u2 + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u3)
+}
\ No newline at end of file
@@ -0,0 +1,86 @@
+use std::f64::consts::PI;
+use std::path::Path;
+
+use image::ImageResult;
+use rand::{thread_rng, Rng};
+
+use crate::vec3::Vec3;
+
+// Shape of the lens opening, which is the shape out-of-focus highlights (bokeh) take on.
+// Samples are points in the unit square around the lens center, scaled by the lens radius.
+pub enum Aperture {
+    Circle,
+    // Regular polygon inscribed in the unit circle, like the blades of a real iris.
+    // `rotation` is in degrees.
+    Polygon { blades: u32, rotation: f64 },
+    Image(BokehImage),
+}
+
+impl Aperture {
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Circle => Vec3::random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => sample_polygon(*blades, rotation.to_radians()),
+            Aperture::Image(image) => image.sample(),
+        }
+    }
+}
+
+// Uniform over the polygon: pick one of its identical triangles, then a point inside it
+fn sample_polygon(blades: u32, rotation: f64) -> Vec3 {
+    let mut rng = thread_rng();
+    let blades = blades.max(3);
+    let k = rng.gen_range(0..blades);
+    let corner = |i: u32| {
+        let angle = rotation + 2.0 * PI * i as f64 / blades as f64;
+        Vec3::new(angle.cos(), angle.sin(), 0)
+    };
+
+    let a = rng.gen::<f64>().sqrt();
+    let b = rng.gen::<f64>();
+    a * (1.0 - b) * corner(k) + a * b * corner(k + 1)
+}
+
+// Grayscale aperture mask covering the unit square, sampled in proportion to its brightness
+pub struct BokehImage {
+    width: u32,
+    height: u32,
+    // Running total of pixel brightness, normalised to end at 1
+    cdf: Vec<f64>,
+}
+
+impl BokehImage {
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let image = image::open(path)?.to_luma8();
+        let mut total = 0.0;
+        let mut cdf: Vec<f64> = image.pixels()
+                                     .map(|p| {
+                                         total += p.0[0] as f64;
+                                         total
+                                     })
+                                     .collect();
+        // An all-black mask would have nowhere to sample, so treat it as fully open
+        if total == 0.0 {
+            cdf.iter_mut().enumerate().for_each(|(i, c)| *c = i as f64 + 1.0);
+            total = cdf.len() as f64;
+        }
+        cdf.iter_mut().for_each(|c| *c /= total);
+
+        Ok(BokehImage {
+            width: image.width(),
+            height: image.height(),
+            cdf,
+        })
+    }
+
+    fn sample(&self) -> Vec3 {
+        let mut rng = thread_rng();
+        let u = rng.gen::<f64>();
+        let index = self.cdf.partition_point(|&c| c <= u).min(self.cdf.len() - 1) as u32;
+
+        let x = (index % self.width) as f64 + rng.gen::<f64>();
+        let y = (index / self.width) as f64 + rng.gen::<f64>();
+        // Image rows run top to bottom, lens v runs up
+        Vec3::new(2.0 * x / self.width as f64 - 1.0, 1.0 - 2.0 * y / self.height as f64, 0)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,85 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Lens distortion in the Brown-Conrady model used by OpenCV and most calibration tools, plus
+// lateral chromatic aberration. Coordinates are normalised image coordinates: positions on
+// the image plane at unit distance in front of the lens, with the optical axis at 0.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LensProfile {
+    // Radial coefficients
+    pub k1: f64,
+    pub k2: f64,
+    pub k3: f64,
+    // Tangential (decentering) coefficients
+    pub p1: f64,
+    pub p2: f64,
+    // How much larger the red and blue images are than the green one, as fractions
+    pub ca_red: f64,
+    pub ca_blue: f64,
+}
+
+impl LensProfile {
+    // Reads `name = value` lines for any of k1, k2, k3, p1, p2, ca_red and ca_blue. Missing
+    // entries are zero, and everything after a # is a comment.
+    pub fn load(path: &Path) -> io::Result<LensProfile> {
+        let mut profile = LensProfile { k1: 0.0, k2: 0.0, k3: 0.0, p1: 0.0, p2: 0.0, ca_red: 0.0, ca_blue: 0.0 };
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue
+            }
+            let (name, value) = line.split_once('=')
+                                    .ok_or_else(|| invalid(format!("expected name = value, got '{}'", line)))?;
+            let value: f64 = value.trim().parse()
+                                  .map_err(|_| invalid(format!("invalid number in '{}'", line)))?;
+            let field = match name.trim() {
+                "k1" => &mut profile.k1,
+                "k2" => &mut profile.k2,
+                "k3" => &mut profile.k3,
+                "p1" => &mut profile.p1,
+                "p2" => &mut profile.p2,
+                "ca_red" => &mut profile.ca_red,
+                "ca_blue" => &mut profile.ca_blue,
+                other => return Err(invalid(format!("unknown lens parameter '{}'", other))),
+            };
+            *field = value;
+        }
+        Ok(profile)
+    }
+
+    pub fn has_chromatic_aberration(&self) -> bool {
+        self.ca_red != 0.0 || self.ca_blue != 0.0
+    }
+
+    // Image scale of color channel 0, 1 or 2 relative to green
+    pub fn channel_scale(&self, channel: usize) -> f64 {
+        match channel {
+            0 => 1.0 + self.ca_red,
+            2 => 1.0 + self.ca_blue,
+            _ => 1.0,
+        }
+    }
+
+    // Where the lens images a point that an ideal lens would put at (x, y)
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        (x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+         y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y)
+    }
+
+    // The inverse of distort, which has no closed form, by fixed-point iteration. This is
+    // what rendering needs: the ideal direction for a point of the distorted image.
+    pub fn undistort(&self, x: f64, y: f64) -> (f64, f64) {
+        let (mut ux, mut uy) = (x, y);
+        for _ in 0..20 {
+            let (dx, dy) = self.distort(ux, uy);
+            ux += x - dx;
+            uy += y - dy;
+        }
+        (ux, uy)
+    }
+}
\ No newline at end of file
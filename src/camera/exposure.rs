@@ -0,0 +1,83 @@
+use std::str::FromStr;
+
+use crate::vec3::Color;
+
+// Physical exposure treats scene units as metres and radiance as luminance in cd/m², seen
+// through a full-frame (36 x 24 mm) sensor.
+const SENSOR_HEIGHT: f64 = 0.024;
+
+// Exposure of a pinhole camera, which has no aperture to measure
+const PINHOLE_F_NUMBER: f64 = 16.0;
+
+// Focal length over aperture diameter, with the focal length that gives a full-frame sensor
+// a vertical field of view of `v_fov` degrees
+pub fn f_number(v_fov: f64, aperture: f64) -> f64 {
+    if aperture <= 0.0 {
+        return PINHOLE_F_NUMBER
+    }
+    let focal_length = SENSOR_HEIGHT / (2.0 * (v_fov.to_radians() / 2.0).tan());
+    focal_length / aperture
+}
+
+// How the sensor turns exposure into pixel values
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SensorResponse {
+    Linear,
+    // S-shaped film characteristic curve in log exposure, mapping middle grey (0.18) to itself
+    // and rolling off smoothly towards black and white
+    Film,
+}
+
+impl SensorResponse {
+    pub fn apply(&self, c: Color) -> Color {
+        match self {
+            SensorResponse::Linear => c,
+            SensorResponse::Film => Color::new(film_curve(c.x()), film_curve(c.y()), film_curve(c.z())),
+        }
+    }
+}
+
+impl FromStr for SensorResponse {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "linear" => Ok(SensorResponse::Linear),
+            "film" => Ok(SensorResponse::Film),
+            _ => Err(format!("Unknown sensor response '{}'", s)),
+        }
+    }
+}
+
+fn film_curve(x: f64) -> f64 {
+    const CONTRAST: f64 = 1.5;
+    const GREY: f64 = 0.18;
+    if x <= 0.0 {
+        return 0.0
+    }
+    let m = GREY.powf(CONTRAST) * (1.0 / GREY - 1.0);
+    let xc = x.powf(CONTRAST);
+    xc / (xc + m)
+}
+
+// Camera exposure settings. Scene luminance is scaled the way a sensor rated by saturation-based
+// ISO speed records it, so 1.0 in the image is the brightest value the sensor can hold.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Exposure {
+    pub f_number: f64,
+    // Shutter time in seconds
+    pub shutter: f64,
+    pub iso: f64,
+}
+
+impl Exposure {
+    // EV at ISO 100: log2(N² / t) adjusted for sensitivity
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter * 100.0 / self.iso).log2()
+    }
+
+    // Factor from scene luminance to sensor value, where 1 is the saturation point
+    pub fn scale(&self) -> f64 {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,288 @@
+pub mod animation;
+pub mod aperture;
+pub mod distortion;
+pub mod exposure;
+pub mod projections;
+pub mod stereo;
+
+use std::str::FromStr;
+
+use rand::{thread_rng, Rng};
+
+use crate::vec3::{Color, Point3, Vec3};
+use crate::ray::Ray;
+use crate::shapes::Hit;
+
+use self::aperture::Aperture;
+use self::distortion::LensProfile;
+
+// Aperture samples tried against the cat's-eye clipping circle before giving up on clipping.
+// The overlap with a circular aperture is at least a seventh of it, so this only runs out
+// when the overlap is (nearly) empty.
+const CAT_EYE_ATTEMPTS: u32 = 256;
+
+// Maps a point on the image to a camera ray. `s` runs from 0 at the left edge to 1 at the
+// right, `t` from 0 at the bottom to 1 at the top.
+pub trait Project: Send + Sync {
+    fn get_ray(&self, s: f64, t: f64) -> Ray;
+
+    // A ray together with the color weight of what it sees, for cameras that trace color
+    // channels along different paths. The weights average to white over many samples.
+    fn get_weighted_ray(&self, s: f64, t: f64) -> (Ray, Color) {
+        (self.get_ray(s, t), Color::new(1, 1, 1))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Fisheye,
+    Equisolid,
+    Equirectangular,
+    CubeMap,
+}
+
+impl Projection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic => "orthographic",
+            Projection::Fisheye => "fisheye",
+            Projection::Equisolid => "equisolid",
+            Projection::Equirectangular => "equirectangular",
+            Projection::CubeMap => "cubemap",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Option<Projection> {
+        [Projection::Perspective, Projection::Orthographic, Projection::Fisheye, Projection::Equisolid,
+         Projection::Equirectangular, Projection::CubeMap]
+            .get(index as usize)
+            .copied()
+    }
+
+    pub fn index(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        (0..)
+            .map_while(Projection::from_index)
+            .find(|projection| projection.name() == s.to_ascii_lowercase())
+            .ok_or(format!("Unknown projection '{}'", s))
+    }
+}
+
+pub struct Camera {
+    origin: Point3,
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    lens_radius: f64,
+    focus_dist: f64,
+    aperture: Aperture,
+    // How far the lens opening is clipped towards the frame edges, see with_cat_eye
+    cat_eye: f64,
+    // Normal of the plane of focus, which is tilted away from the view direction by with_tilt_shift
+    focus_normal: Vec3,
+    lens: Option<LensProfile>,
+}
+
+impl Camera {
+
+    pub fn new(look_from: Point3, 
+               loot_at: Point3, 
+               v_up: Vec3, 
+               v_fov: f64, 
+               aspect_ratio: f64,
+               aperture: f64,
+               focus_dist: f64) -> Camera {
+                
+        let theta = v_fov.to_radians();
+        let viewport_height = 2.0 * (theta/2.0).tan();
+        let viewport_width = aspect_ratio * viewport_height;
+                
+        let w = (look_from - loot_at).unit_vector();
+        let u = v_up.cross(&w).unit_vector();
+        let v = w.cross(&u);
+
+        let horizontal = focus_dist * viewport_width * u;
+        let vertical = focus_dist * viewport_height * v;
+        let lower_left_corner = look_from - horizontal/2.0 - vertical/2.0 - focus_dist*w;
+
+        Camera {
+            origin: look_from,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            u, v, w,
+            lens_radius: aperture/2.0,
+            focus_dist,
+            aperture: Aperture::Circle,
+            cat_eye: 0.0,
+            focus_normal: w,
+            lens: None,
+        }
+    }
+
+    pub fn with_aperture(self, aperture: Aperture) -> Camera {
+        Camera { aperture, ..self }
+    }
+
+    // Cat's-eye bokeh: towards the frame edges the lens opening is cut by a second circle,
+    // as if by the rim of a rear lens element, offset by `strength` times the distance from
+    // the frame center (0 disables, 1 leaves a half-moon in the corners)
+    pub fn with_cat_eye(self, strength: f64) -> Camera {
+        Camera { cat_eye: strength.clamp(0.0, 1.0), ..self }
+    }
+
+    // Tilt rotates the plane of focus about the camera's horizontal and vertical axes, in
+    // degrees, so it no longer faces the camera (the miniature look). Shift slides the frame
+    // across the image plane, in fractions of its width and height, without turning the camera.
+    pub fn with_tilt_shift(self, tilt_x: f64, tilt_y: f64, shift_x: f64, shift_y: f64) -> Camera {
+        let (sin_x, cos_x) = tilt_x.to_radians().sin_cos();
+        let (sin_y, cos_y) = tilt_y.to_radians().sin_cos();
+        let tilted = cos_x * self.w + sin_x * self.v;
+        let focus_normal = (cos_y * tilted + sin_y * self.u).unit_vector();
+
+        Camera {
+            lower_left_corner: self.lower_left_corner + shift_x * self.horizontal + shift_y * self.vertical,
+            focus_normal,
+            ..self
+        }
+    }
+
+    // Distorts the image like a real lens, and with chromatic aberration traces each color
+    // channel through its own, slightly scaled, image
+    pub fn with_lens_profile(self, lens: LensProfile) -> Camera {
+        Camera { lens: Some(lens), ..self }
+    }
+
+    // Focuses on whatever is visible through the image point (s, t), using a pinhole ray from
+    // the lens center. Keeps the current focus distance if the ray hits nothing.
+    pub fn autofocus(self, world: &dyn Hit, s: f64, t: f64) -> Camera {
+        let through = self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin;
+        let rec = match world.hit(&Ray::new(self.origin, through), 0.0001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => return self,
+        };
+
+        // Focus distance is measured along the view axis, and the frame scales with it
+        let distance = (self.origin - rec.get_p()).dot(&self.w);
+        if distance <= 0.0 {
+            return self
+        }
+        let scale = distance / self.focus_dist;
+        Camera {
+            lower_left_corner: self.origin + (self.lower_left_corner - self.origin) * scale,
+            horizontal: self.horizontal * scale,
+            vertical: self.vertical * scale,
+            focus_dist: distance,
+            ..self
+        }
+    }
+
+    // One eye of a stereo pair: moved `offset` along the camera's horizontal axis, still facing
+    // the same way, with the frame shifted so both eyes' frames line up at the `convergence`
+    // distance. Objects there appear at screen depth.
+    pub fn with_eye_offset(self, offset: f64, convergence: f64) -> Camera {
+        let moved = offset * self.u;
+        Camera {
+            origin: self.origin + moved,
+            lower_left_corner: self.lower_left_corner + moved - (offset * self.focus_dist / convergence) * self.u,
+            ..self
+        }
+    }
+
+    fn sample_lens(&self, s: f64, t: f64) -> Vec3 {
+        if self.cat_eye == 0.0 {
+            return self.aperture.sample()
+        }
+
+        // Rejection sample the overlap with the clipping circle. For a circular aperture it is
+        // never empty, but a polygon or bokeh image can miss the clipping circle entirely, so
+        // after enough misses the sample goes unclipped.
+        let mut clip = self.cat_eye * Vec3::new(2.0 * s - 1.0, 2.0 * t - 1.0, 0);
+        if clip.length() > 1.5 {
+            clip = 1.5 * clip.unit_vector();
+        }
+        for _ in 0..CAT_EYE_ATTEMPTS {
+            let p = self.aperture.sample();
+            if (p - clip).length_squared() <= 1.0 {
+                return p
+            }
+        }
+        self.aperture.sample()
+    }
+
+    // Ray through the image point (s, t) for a channel whose image is `channel_scale` times the
+    // size of the green one
+    fn lens_ray(&self, s: f64, t: f64, channel_scale: f64) -> Ray {
+        let mut through = self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin;
+
+        if let Some(lens) = &self.lens {
+            // Normalised coordinates relative to the optical axis, which a shifted frame is off
+            let axis = -self.focus_dist * self.w;
+            let x = (through - axis).dot(&self.u) / self.focus_dist / channel_scale;
+            let y = (through - axis).dot(&self.v) / self.focus_dist / channel_scale;
+            let (x, y) = lens.undistort(x, y);
+            through = axis + self.focus_dist * (x * self.u + y * self.v);
+        }
+
+        let rd = self.lens_radius * self.sample_lens(s, t);
+        let offset = self.u * rd.x() + self.v * rd.y();
+
+        // Where the ray through the lens center meets the plane of focus. Every ray through the
+        // lens aims for that point, so it is the only one in focus.
+        let plane_distance = self.focus_dist * self.w.dot(&self.focus_normal);
+        let focus_point = self.origin + through * (-plane_distance / through.dot(&self.focus_normal));
+
+        Ray::new(self.origin + offset, focus_point - self.origin - offset)
+    }
+}
+
+impl Project for Camera {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        self.lens_ray(s, t, 1.0)
+    }
+
+    fn get_weighted_ray(&self, s: f64, t: f64) -> (Ray, Color) {
+        match &self.lens {
+            Some(lens) if lens.has_chromatic_aberration() => {
+                // One channel per ray, picked at random, so its weight is tripled
+                let channel = thread_rng().gen_range(0..3);
+                let mut weight = [0.0; 3];
+                weight[channel] = 3.0;
+                (self.lens_ray(s, t, lens.channel_scale(channel)), Color::new(weight[0], weight[1], weight[2]))
+            }
+            _ => (self.get_ray(s, t), Color::new(1, 1, 1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cat_eye_clipping_terminates_for_polygons() {
+        // With an edge midpoint facing away from the bottom left corner, only a thin sliver of
+        // the triangle lies inside that corner's clipping circle
+        let cam = Camera::new(Point3::new(0, 0, 1), Point3::zero(), Vec3::new(0, 1, 0), 90.0, 1.0, 0.2, 1.0)
+            .with_aperture(Aperture::Polygon { blades: 3, rotation: 165.0 })
+            .with_cat_eye(4.0);
+        assert_eq!(cam.cat_eye, 1.0);
+
+        for (s, t) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0), (0.0, 0.5)] {
+            assert!(cam.sample_lens(s, t).length() <= 1.0 + 1e-9);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,159 @@
+use std::f64::consts::PI;
+
+use crate::camera::Project;
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+// Camera position and orientation shared by the projections below. Like Camera, it looks
+// along -w with u to the right and v up.
+pub struct Frame {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Frame {
+    pub fn new(look_from: Point3, look_at: Point3, v_up: Vec3) -> Frame {
+        let w = (look_from - look_at).unit_vector();
+        let u = v_up.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        Frame { origin: look_from, u, v, w }
+    }
+
+    // The same frame moved `offset` to the right, for the eyes of a parallel stereo pair
+    pub fn with_eye_offset(self, offset: f64) -> Frame {
+        Frame { origin: self.origin + offset * self.u, ..self }
+    }
+
+    // Direction from right, up and forward components
+    fn direction(&self, right: f64, up: f64, forward: f64) -> Vec3 {
+        right * self.u + up * self.v - forward * self.w
+    }
+}
+
+// Parallel rays through a view `view_height` world units tall, so nothing shrinks with distance
+pub struct Orthographic {
+    frame: Frame,
+    width: f64,
+    height: f64,
+}
+
+impl Orthographic {
+    pub fn new(frame: Frame, view_height: f64, aspect_ratio: f64) -> Orthographic {
+        Orthographic { frame, width: view_height * aspect_ratio, height: view_height }
+    }
+}
+
+impl Project for Orthographic {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let f = &self.frame;
+        let origin = f.origin + (s - 0.5) * self.width * f.u + (t - 0.5) * self.height * f.v;
+        Ray::new(origin, f.direction(0.0, 0.0, 1.0))
+    }
+}
+
+// How a fisheye lens maps the angle from its axis to distance from the image center
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FisheyeMapping {
+    // Distance proportional to angle
+    Equidistant,
+    // Equal solid angles cover equal image areas
+    Equisolid,
+}
+
+// Fisheye lens whose image circle spans the frame height and covers `v_fov` degrees. The frame
+// corners see past the image circle, like a full-frame fisheye.
+pub struct Fisheye {
+    frame: Frame,
+    half_fov: f64,
+    aspect_ratio: f64,
+    mapping: FisheyeMapping,
+}
+
+impl Fisheye {
+    pub fn new(frame: Frame, v_fov: f64, aspect_ratio: f64, mapping: FisheyeMapping) -> Fisheye {
+        Fisheye { frame, half_fov: v_fov.to_radians() / 2.0, aspect_ratio, mapping }
+    }
+}
+
+impl Project for Fisheye {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let x = (2.0 * s - 1.0) * self.aspect_ratio;
+        let y = 2.0 * t - 1.0;
+        let r = (x * x + y * y).sqrt();
+
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.half_fov,
+            FisheyeMapping::Equisolid => 2.0 * (r * (self.half_fov / 2.0).sin()).min(1.0).asin(),
+        }.min(PI);
+        let phi = y.atan2(x);
+
+        let direction = self.frame.direction(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+        Ray::new(self.frame.origin, direction)
+    }
+}
+
+// Full 360 by 180 degree panorama in longitude and latitude, looking forward at its center
+pub struct Equirectangular {
+    frame: Frame,
+    eye_offset: f64,
+}
+
+impl Equirectangular {
+    pub fn new(frame: Frame) -> Equirectangular {
+        Equirectangular { frame, eye_offset: 0.0 }
+    }
+
+    // One eye of an omni-directional stereo panorama. Rays start `offset` to the right of the
+    // frame origin relative to their own horizontal direction, so every longitude sees a
+    // correct stereo pair, converging at infinity.
+    pub fn with_eye_offset(self, offset: f64) -> Equirectangular {
+        Equirectangular { eye_offset: offset, ..self }
+    }
+}
+
+impl Project for Equirectangular {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let longitude = (s - 0.5) * 2.0 * PI;
+        let latitude = (t - 0.5) * PI;
+        let direction = self.frame.direction(latitude.cos() * longitude.sin(),
+                                             latitude.sin(),
+                                             latitude.cos() * longitude.cos());
+        let eye = self.eye_offset * self.frame.direction(longitude.cos(), 0.0, -longitude.sin());
+        Ray::new(self.frame.origin + eye, direction)
+    }
+}
+
+// Six 90 degree faces laid out in a 3 by 2 grid, so use a 3:2 image for square faces.
+// Top row: left, front, right. Bottom row: back, down, up.
+pub struct CubeMap {
+    frame: Frame,
+}
+
+impl CubeMap {
+    pub fn new(frame: Frame) -> CubeMap {
+        CubeMap { frame }
+    }
+}
+
+impl Project for CubeMap {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let column = ((s * 3.0) as u32).min(2);
+        let row = ((t * 2.0) as u32).min(1);
+        // Position within the face, from -1 to 1
+        let a = 2.0 * (s * 3.0 - column as f64) - 1.0;
+        let b = 2.0 * (t * 2.0 - row as f64) - 1.0;
+
+        let f = &self.frame;
+        let direction = match (row, column) {
+            (1, 0) => f.direction(-1.0, b, a),
+            (1, 1) => f.direction(a, b, 1.0),
+            (1, _) => f.direction(1.0, b, -a),
+            (0, 0) => f.direction(-a, b, -1.0),
+            (0, 1) => f.direction(a, -1.0, b),
+            _ => f.direction(a, 1.0, -b),
+        };
+        Ray::new(f.origin, direction)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use crate::camera::Project;
+use crate::ray::Ray;
+use crate::vec3::Color;
+
+// How the two eyes share the image
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StereoLayout {
+    // Left eye in the left half
+    SideBySide,
+    // Left eye in the top half
+    TopBottom,
+}
+
+impl StereoLayout {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StereoLayout::SideBySide => "side-by-side",
+            StereoLayout::TopBottom => "top-bottom",
+        }
+    }
+
+    pub fn from_index(index: u32) -> Option<StereoLayout> {
+        [StereoLayout::SideBySide, StereoLayout::TopBottom].get(index as usize).copied()
+    }
+
+    pub fn index(&self) -> u32 {
+        *self as u32
+    }
+
+    // Image width over height for the whole image, when each eye has `eye_aspect_ratio`
+    pub fn aspect_ratio(&self, eye_aspect_ratio: f64) -> f64 {
+        match self {
+            StereoLayout::SideBySide => 2.0 * eye_aspect_ratio,
+            StereoLayout::TopBottom => eye_aspect_ratio / 2.0,
+        }
+    }
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        (0..)
+            .map_while(StereoLayout::from_index)
+            .find(|layout| layout.name() == s.to_ascii_lowercase())
+            .ok_or(format!("Unknown stereo layout '{}'", s))
+    }
+}
+
+// Eye separation and convergence distance of a stereo pair, in world units
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StereoRig {
+    pub layout: StereoLayout,
+    pub interocular: f64,
+    pub convergence: f64,
+}
+
+// Renders a left and right eye into the two halves of one image
+pub struct Stereo {
+    left: Box<dyn Project>,
+    right: Box<dyn Project>,
+    layout: StereoLayout,
+}
+
+impl Stereo {
+    pub fn new(left: Box<dyn Project>, right: Box<dyn Project>, layout: StereoLayout) -> Stereo {
+        Stereo { left, right, layout }
+    }
+}
+
+impl Stereo {
+    // The eye covering (s, t), and the point within that eye's image
+    fn eye(&self, s: f64, t: f64) -> (&dyn Project, f64, f64) {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => (self.left.as_ref(), 2.0 * s, t),
+            StereoLayout::SideBySide => (self.right.as_ref(), 2.0 * s - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => (self.left.as_ref(), s, 2.0 * t - 1.0),
+            StereoLayout::TopBottom => (self.right.as_ref(), s, 2.0 * t),
+        }
+    }
+}
+
+impl Project for Stereo {
+    fn get_ray(&self, s: f64, t: f64) -> Ray {
+        let (eye, s, t) = self.eye(s, t);
+        eye.get_ray(s, t)
+    }
+
+    fn get_weighted_ray(&self, s: f64, t: f64) -> (Ray, Color) {
+        let (eye, s, t) = self.eye(s, t);
+        eye.get_weighted_ray(s, t)
+    }
+}
\ No newline at end of file
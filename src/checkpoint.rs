@@ -0,0 +1,257 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::camera::Projection;
+use crate::camera::distortion::LensProfile;
+use crate::camera::stereo::{StereoLayout, StereoRig};
+use crate::film::{AovSample, Film};
+use crate::filter::{Filter, FilterKind};
+use crate::render::{Crop, RenderSettings};
+use crate::vec3::Vec3;
+
+// Checkpoint file layout, all little-endian:
+//   magic "RTIOWCKP", format version (u32)
+//   width (u32), height (u32), samples per pixel (u64), max depth (i32), scene seed (u64),
+//   reconstruction filter (u32 kind, f64 radius), spectral mode (u8), camera projection (u32),
+//   stereo layout (u32, 0 for mono, otherwise layout + 1), interocular distance (f64),
+//   convergence distance (f64), crop window (u8 present, then x, y, width, height as u32),
+//   lens profile (u8 present, then k1, k2, k3, p1, p2, ca_red, ca_blue as f64),
+//   autofocus point (u8 present, then x, y as f64)
+//   per pixel, in film order: filter-weighted linear radiance (3 x f64), filter weight (f64),
+//   samples taken (u64),
+//   then the AOVs: AOV samples taken (u64), albedo sum (3 x f64), normal sum (3 x f64), depth (f64), position (3 x f64),
+//   object ID (u32), material ID (u32)
+
+const MAGIC: &[u8; 8] = b"RTIOWCKP";
+const VERSION: u32 = 9;
+
+pub fn save(path: &Path, settings: &RenderSettings, film: &Film) -> io::Result<()> {
+    // Write beside the target and rename over it, so a kill mid-write never leaves a
+    // truncated checkpoint behind
+    let tmp_path = path.with_extension("tmp");
+    let mut file = BufWriter::new(File::create(&tmp_path)?);
+
+    file.write_all(MAGIC)?;
+    file.write_all(&VERSION.to_le_bytes())?;
+    file.write_all(&settings.width.to_le_bytes())?;
+    file.write_all(&settings.height.to_le_bytes())?;
+    file.write_all(&settings.samples_per_pixel.to_le_bytes())?;
+    file.write_all(&settings.max_depth.to_le_bytes())?;
+    file.write_all(&settings.seed.to_le_bytes())?;
+    file.write_all(&settings.filter.kind().index().to_le_bytes())?;
+    file.write_all(&settings.filter.radius().to_le_bytes())?;
+    file.write_all(&[settings.spectral as u8])?;
+    file.write_all(&settings.projection.index().to_le_bytes())?;
+    let rig = settings.stereo.unwrap_or(StereoRig { layout: StereoLayout::SideBySide, interocular: 0.0, convergence: 0.0 });
+    let layout = settings.stereo.map_or(0, |rig| rig.layout.index() + 1);
+    file.write_all(&layout.to_le_bytes())?;
+    file.write_all(&rig.interocular.to_le_bytes())?;
+    file.write_all(&rig.convergence.to_le_bytes())?;
+    let crop = settings.crop.unwrap_or(Crop { x: 0, y: 0, width: 0, height: 0 });
+    file.write_all(&[settings.crop.is_some() as u8])?;
+    for value in [crop.x, crop.y, crop.width, crop.height] {
+        file.write_all(&value.to_le_bytes())?;
+    }
+    let lens = settings.lens.unwrap_or(LensProfile { k1: 0.0, k2: 0.0, k3: 0.0, p1: 0.0, p2: 0.0, ca_red: 0.0, ca_blue: 0.0 });
+    file.write_all(&[settings.lens.is_some() as u8])?;
+    for value in [lens.k1, lens.k2, lens.k3, lens.p1, lens.p2, lens.ca_red, lens.ca_blue] {
+        file.write_all(&value.to_le_bytes())?;
+    }
+    let (focus_x, focus_y) = settings.autofocus.unwrap_or((0.0, 0.0));
+    file.write_all(&[settings.autofocus.is_some() as u8])?;
+    file.write_all(&focus_x.to_le_bytes())?;
+    file.write_all(&focus_y.to_le_bytes())?;
+
+    let pixels = film.sums().iter()
+                     .zip(film.weights())
+                     .zip(film.sample_counts())
+                     .zip(film.aov_samples());
+    for (((sum, weight), samples), aov) in pixels {
+        write_vec3(&mut file, sum)?;
+        file.write_all(&weight.to_le_bytes())?;
+        file.write_all(&samples.to_le_bytes())?;
+        file.write_all(&aov.samples.to_le_bytes())?;
+        write_vec3(&mut file, &aov.albedo)?;
+        write_vec3(&mut file, &aov.normal)?;
+        file.write_all(&aov.depth.to_le_bytes())?;
+        write_vec3(&mut file, &aov.position)?;
+        file.write_all(&aov.object_id.to_le_bytes())?;
+        file.write_all(&aov.material_id.to_le_bytes())?;
+    }
+
+    file.flush()?;
+    drop(file);
+    fs::rename(tmp_path, path)
+}
+
+pub fn load(path: &Path) -> io::Result<(RenderSettings, Film)> {
+    let mut file = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a render checkpoint"))
+    }
+    if read_u32(&mut file)? != VERSION {
+        return Err(invalid_data("unsupported checkpoint version"))
+    }
+
+    let settings = RenderSettings {
+        width: read_u32(&mut file)?,
+        height: read_u32(&mut file)?,
+        samples_per_pixel: read_u64(&mut file)?,
+        max_depth: read_u32(&mut file)? as i32,
+        seed: read_u64(&mut file)?,
+        filter: {
+            let kind = FilterKind::from_index(read_u32(&mut file)?)
+                .ok_or_else(|| invalid_data("unknown reconstruction filter"))?;
+            Filter::new(kind, read_f64(&mut file)?)
+        },
+        spectral: {
+            let mut flag = [0; 1];
+            file.read_exact(&mut flag)?;
+            flag[0] != 0
+        },
+        projection: Projection::from_index(read_u32(&mut file)?)
+            .ok_or_else(|| invalid_data("unknown camera projection"))?,
+        stereo: {
+            let layout = read_u32(&mut file)?;
+            let interocular = read_f64(&mut file)?;
+            let convergence = read_f64(&mut file)?;
+            match layout {
+                0 => None,
+                index => Some(StereoRig {
+                    layout: StereoLayout::from_index(index - 1)
+                        .ok_or_else(|| invalid_data("unknown stereo layout"))?,
+                    interocular,
+                    convergence,
+                }),
+            }
+        },
+        crop: {
+            let mut flag = [0; 1];
+            file.read_exact(&mut flag)?;
+            let crop = Crop {
+                x: read_u32(&mut file)?,
+                y: read_u32(&mut file)?,
+                width: read_u32(&mut file)?,
+                height: read_u32(&mut file)?,
+            };
+            (flag[0] != 0).then_some(crop)
+        },
+        lens: {
+            let mut flag = [0; 1];
+            file.read_exact(&mut flag)?;
+            let lens = LensProfile {
+                k1: read_f64(&mut file)?,
+                k2: read_f64(&mut file)?,
+                k3: read_f64(&mut file)?,
+                p1: read_f64(&mut file)?,
+                p2: read_f64(&mut file)?,
+                ca_red: read_f64(&mut file)?,
+                ca_blue: read_f64(&mut file)?,
+            };
+            (flag[0] != 0).then_some(lens)
+        },
+        autofocus: {
+            let mut flag = [0; 1];
+            file.read_exact(&mut flag)?;
+            let point = (read_f64(&mut file)?, read_f64(&mut file)?);
+            (flag[0] != 0).then_some(point)
+        },
+    };
+
+    let (width, height) = settings.film_size();
+    let len = (width * height) as usize;
+    let mut sum = Vec::with_capacity(len);
+    let mut weight = Vec::with_capacity(len);
+    let mut samples = Vec::with_capacity(len);
+    let mut aovs = Vec::with_capacity(len);
+    for _ in 0..len {
+        sum.push(read_vec3(&mut file)?);
+        weight.push(read_f64(&mut file)?);
+        samples.push(read_u64(&mut file)?);
+        aovs.push(AovSample {
+            samples: read_u64(&mut file)?,
+            albedo: read_vec3(&mut file)?,
+            normal: read_vec3(&mut file)?,
+            depth: read_f64(&mut file)?,
+            position: read_vec3(&mut file)?,
+            object_id: read_u32(&mut file)?,
+            material_id: read_u32(&mut file)?,
+        });
+    }
+
+    let film = Film::from_parts(width, height, sum, weight, samples, aovs);
+    Ok((settings, film))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_vec3<W: Write>(w: &mut W, v: &Vec3) -> io::Result<()> {
+    w.write_all(&v.x().to_le_bytes())?;
+    w.write_all(&v.y().to_le_bytes())?;
+    w.write_all(&v.z().to_le_bytes())
+}
+
+fn read_vec3<R: Read>(r: &mut R) -> io::Result<Vec3> {
+    let x = read_f64(r)?;
+    let y = read_f64(r)?;
+    let z = read_f64(r)?;
+    Ok(Vec3::new(x, y, z))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64<R: Read>(r: &mut R) -> io::Result<f64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_settings_survive_a_round_trip() {
+        let lens = LensProfile { k1: 0.1, k2: -0.02, k3: 0.0, p1: 0.001, p2: 0.0, ca_red: 0.01, ca_blue: -0.01 };
+        let settings = RenderSettings {
+            width: 6,
+            height: 4,
+            samples_per_pixel: 10,
+            max_depth: 50,
+            seed: 7,
+            filter: Filter::new(FilterKind::Gaussian, 1.5),
+            spectral: false,
+            projection: Projection::Perspective,
+            stereo: None,
+            crop: Some(Crop { x: 1, y: 1, width: 3, height: 2 }),
+            lens: Some(lens),
+            autofocus: Some((0.25, 0.75)),
+        };
+        let path = std::env::temp_dir().join(format!("rtiow-test-{}.ckpt", std::process::id()));
+        save(&path, &settings, &Film::new(3, 2)).unwrap();
+        let (loaded, film) = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.crop, settings.crop);
+        assert_eq!(loaded.lens, Some(lens));
+        assert_eq!(loaded.autofocus, Some((0.25, 0.75)));
+        assert_eq!((film.width(), film.height()), (3, 2));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,310 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::camera::Projection;
+use crate::camera::exposure::SensorResponse;
+use crate::camera::stereo::StereoLayout;
+use crate::film::Aov;
+use crate::filter::FilterKind;
+use crate::output::OutputSettings;
+use crate::render::Crop;
+use crate::tonemap::{Operator, ToneMap};
+
+const USAGE: &str = "\
+Usage: rtiow [options]
+
+Options:
+    -o, --output <file>     Output image; the format is chosen by extension
+                            (.png, .exr, .hdr, .pfm, ...) [default: image.png]
+    --tonemap <operator>    Tone mapping for 8-bit output: clamp, reinhard,
+                            reinhard-extended or aces [default: clamp]
+    --exposure <stops>      Exposure adjustment applied before tone mapping
+                            [default: 0]
+    --white <luminance>     White point for reinhard-extended [default: 4]
+    --embed-colorspace      Record the sRGB color space in PNG and EXR metadata
+    --width <pixels>        Image width [default: 200]
+    --samples <count>       Samples per pixel [default: 100]
+    --seed <number>         Seed for the random scene [default: random]
+    --checkpoint <file>     Periodically save render progress to this file
+    --checkpoint-interval <seconds>
+                            Time between checkpoints [default: 60]
+    --resume <file>         Continue a render from a checkpoint, up to
+                            --samples if given
+    --aov <passes>          Comma separated extra passes to write next to the
+                            output: albedo, normal, depth, position,
+                            object_id, material_id
+    --denoise               Filter noise from the render, guided by the
+                            albedo, normal and depth passes
+    --denoise-radius <pixels>
+                            Size of the denoising filter [default: 2]
+    --filter <name>         Pixel reconstruction filter: box, tent, gaussian,
+                            mitchell or lanczos [default: box]
+    --filter-radius <pixels>
+                            Filter radius, at least 0.5 [default: 0.5 box,
+                            1 tent, 1.5 gaussian, 2 mitchell and lanczos]
+    --spectral              Trace one wavelength per path so dispersive glass
+                            splits light into colors
+    --projection <name>     Camera projection: perspective, orthographic,
+                            fisheye, equisolid, equirectangular or cubemap
+                            [default: perspective]
+    --stereo <layout>       Render a stereo pair, side-by-side or top-bottom.
+                            Equirectangular renders omni-directional stereo
+    --interocular <distance>
+                            Distance between the eyes [default: 0.1]
+    --convergence <distance>
+                            Distance at which the eyes' views meet, for the
+                            perspective camera [default: 10]
+    --autofocus <x,y>       Focus on whatever is visible at this point of the
+                            image, as fractions of its width and height from
+                            the top left, e.g. 0.5,0.5 for the center
+    --shutter <seconds>     Expose like a physical camera with this shutter
+                            time, treating scene values as luminance in cd/m²
+                            [default: 1/125 when --iso is given]
+    --iso <speed>           Sensor sensitivity for physical exposure
+                            [default: 100 when --shutter is given]
+    --response <curve>      Sensor response: linear or film [default: linear]
+    --lens-profile <file>   Distort the perspective camera's image with the
+                            Brown-Conrady and chromatic aberration
+                            coefficients in this file
+    --frames <count>        Render a turntable animation as a numbered image
+                            sequence, e.g. image_0001.png, image_0002.png
+    --crop <x,y,width,height>
+                            Only render this region of the image, in pixels
+                            from its top left corner
+    --debug-pixel <x,y>     Print every bounce of the paths through this
+                            pixel instead of rendering, one path unless
+                            --samples is given
+    --bench                 Time ray intersection against the scene with and
+                            without sphere packets instead of rendering
+    -h, --help              Print this message";
+
+pub struct Options {
+    pub output: PathBuf,
+    pub tonemap: Operator,
+    pub exposure: f64,
+    pub white_point: f64,
+    pub embed_color_space: bool,
+    pub width: Option<u32>,
+    pub samples: Option<u64>,
+    pub seed: Option<u64>,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: u64,
+    pub resume: Option<PathBuf>,
+    pub aovs: Vec<Aov>,
+    pub denoise: bool,
+    pub denoise_radius: u32,
+    pub filter: FilterKind,
+    pub filter_radius: Option<f64>,
+    pub spectral: bool,
+    pub projection: Projection,
+    pub stereo: Option<StereoLayout>,
+    pub interocular: f64,
+    pub convergence: f64,
+    pub frames: Option<u32>,
+    pub autofocus: Option<(f64, f64)>,
+    pub shutter: Option<f64>,
+    pub iso: Option<f64>,
+    pub response: SensorResponse,
+    pub lens_profile: Option<PathBuf>,
+    pub crop: Option<Crop>,
+    pub debug_pixel: Option<(u32, u32)>,
+    pub bench: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            output: PathBuf::from("image.png"),
+            tonemap: Operator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+            embed_color_space: false,
+            width: None,
+            samples: None,
+            seed: None,
+            checkpoint: None,
+            checkpoint_interval: 60,
+            resume: None,
+            aovs: Vec::new(),
+            denoise: false,
+            denoise_radius: 2,
+            filter: FilterKind::Box,
+            filter_radius: None,
+            spectral: false,
+            projection: Projection::Perspective,
+            stereo: None,
+            interocular: 0.1,
+            convergence: 10.0,
+            frames: None,
+            autofocus: None,
+            shutter: None,
+            iso: None,
+            response: SensorResponse::Linear,
+            lens_profile: None,
+            crop: None,
+            debug_pixel: None,
+            bench: false,
+        }
+    }
+}
+
+impl Options {
+
+    pub fn from_args() -> Options {
+        match Options::parse(std::env::args().skip(1)) {
+            Ok(opts) => opts,
+            Err(msg) => exit_with_usage(&msg),
+        }
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut opts = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => opts.output = value(&arg, &mut args)?,
+                "--tonemap" => opts.tonemap = value(&arg, &mut args)?,
+                "--exposure" => opts.exposure = value(&arg, &mut args)?,
+                "--white" => opts.white_point = value(&arg, &mut args)?,
+                "--embed-colorspace" => opts.embed_color_space = true,
+                "--width" => opts.width = Some(value(&arg, &mut args)?),
+                "--samples" => opts.samples = Some(value(&arg, &mut args)?),
+                "--seed" => opts.seed = Some(value(&arg, &mut args)?),
+                "--checkpoint" => opts.checkpoint = Some(value(&arg, &mut args)?),
+                "--checkpoint-interval" => opts.checkpoint_interval = value(&arg, &mut args)?,
+                "--resume" => opts.resume = Some(value(&arg, &mut args)?),
+                "--aov" => {
+                    let list: String = value(&arg, &mut args)?;
+                    for name in list.split(',') {
+                        opts.aovs.push(name.trim().parse()?);
+                    }
+                }
+                "--denoise" => opts.denoise = true,
+                "--denoise-radius" => opts.denoise_radius = value(&arg, &mut args)?,
+                "--filter" => opts.filter = value(&arg, &mut args)?,
+                "--filter-radius" => opts.filter_radius = Some(value(&arg, &mut args)?),
+                "--spectral" => opts.spectral = true,
+                "--projection" => opts.projection = value(&arg, &mut args)?,
+                "--stereo" => opts.stereo = Some(value(&arg, &mut args)?),
+                "--interocular" => opts.interocular = value(&arg, &mut args)?,
+                "--convergence" => opts.convergence = value(&arg, &mut args)?,
+                "--shutter" => {
+                    // Accept fractions like 1/125 as well as plain seconds
+                    let time: String = value(&arg, &mut args)?;
+                    let seconds = match time.split_once('/') {
+                        Some((n, d)) => n.trim().parse::<f64>().ok().zip(d.trim().parse::<f64>().ok()).map(|(n, d)| n / d),
+                        None => time.trim().parse().ok(),
+                    };
+                    opts.shutter = Some(seconds.filter(|s| *s > 0.0)
+                                               .ok_or(format!("Invalid shutter time '{}'", time))?);
+                }
+                "--iso" => opts.iso = Some(value(&arg, &mut args)?),
+                "--response" => opts.response = value(&arg, &mut args)?,
+                "--lens-profile" => opts.lens_profile = Some(value(&arg, &mut args)?),
+                "--frames" => opts.frames = Some(value(&arg, &mut args)?),
+                "--autofocus" => {
+                    let point: String = value(&arg, &mut args)?;
+                    let parse = |v: Option<&str>| v.and_then(|v| v.trim().parse::<f64>().ok());
+                    let mut coords = point.split(',');
+                    match (parse(coords.next()), parse(coords.next()), coords.next()) {
+                        (Some(x), Some(y), None) => opts.autofocus = Some((x, y)),
+                        _ => return Err(format!("Invalid autofocus point '{}', expected x,y", point)),
+                    }
+                }
+                "--crop" => {
+                    let [x, y, width, height] = list(&arg, &mut args)?;
+                    if width == 0 || height == 0 {
+                        return Err("The crop window must not be empty".to_string())
+                    }
+                    opts.crop = Some(Crop { x, y, width, height });
+                }
+                "--debug-pixel" => {
+                    let [x, y] = list(&arg, &mut args)?;
+                    opts.debug_pixel = Some((x, y));
+                }
+                "--bench" => opts.bench = true,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+
+        // Pixel positions are spread over width - 1 and height - 1 steps
+        if opts.width.is_some_and(|width| width < 2) {
+            return Err("The image must be at least 2 pixels wide".to_string())
+        }
+        // Samples only reach pixels within the radius, so under half a pixel some get none
+        if let Some(radius) = opts.filter_radius {
+            if !radius.is_finite() || radius < 0.5 {
+                return Err(format!("Invalid filter radius {}, it must be at least 0.5 pixels", radius))
+            }
+        }
+        // The eyes' views only meet in front of the camera
+        if !opts.convergence.is_finite() || opts.convergence <= 0.0 {
+            return Err(format!("Invalid convergence distance {}, it must be positive", opts.convergence))
+        }
+        if !opts.interocular.is_finite() || opts.interocular < 0.0 {
+            return Err(format!("Invalid interocular distance {}, it can't be negative", opts.interocular))
+        }
+        if opts.frames.is_some() && (opts.checkpoint.is_some() || opts.resume.is_some()) {
+            return Err("--frames can't be combined with checkpoints".to_string())
+        }
+        Ok(opts)
+    }
+
+    pub fn output_settings(&self) -> OutputSettings {
+        OutputSettings {
+            tone_map: ToneMap::new(self.tonemap, self.exposure, self.white_point),
+            embed_color_space: self.embed_color_space,
+        }
+    }
+}
+
+// Reports an invalid command line, for problems only found after parsing, and exits
+pub fn exit_with_usage(msg: &str) -> ! {
+    eprintln!("{}\n\n{}", msg, USAGE);
+    std::process::exit(1);
+}
+
+fn value<T: FromStr, I: Iterator<Item = String>>(flag: &str, args: &mut I) -> Result<T, String> {
+    let raw = args.next().ok_or(format!("Missing value for '{}'", flag))?;
+    raw.parse().map_err(|_| format!("Invalid value '{}' for '{}'", raw, flag))
+}
+
+
+// Comma separated list of exactly N values
+fn list<T: FromStr, I: Iterator<Item = String>, const N: usize>(flag: &str, args: &mut I) -> Result<[T; N], String> {
+    let raw: String = value(flag, args)?;
+    let invalid = || format!("Invalid value '{}' for '{}', expected {} comma separated numbers", raw, flag, N);
+    let values: Vec<T> = raw.split(',')
+                            .map(|v| v.trim().parse().map_err(|_| invalid()))
+                            .collect::<Result<_, _>>()?;
+    values.try_into().map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn filter_radius_must_cover_the_pixel() {
+        assert!(parse("--filter-radius 0").is_err());
+        assert!(parse("--filter-radius -1").is_err());
+        assert!(parse("--filter box --filter-radius 0.25").is_err());
+        assert_eq!(parse("--filter gaussian --filter-radius 0.5").unwrap().filter_radius, Some(0.5));
+    }
+
+    #[test]
+    fn stereo_distances_must_make_sense() {
+        assert!(parse("--convergence 0").is_err());
+        assert!(parse("--convergence -5").is_err());
+        assert!(parse("--interocular -0.1").is_err());
+        assert!(parse("--interocular 0 --convergence 0.5").is_ok());
+    }
+}
\ No newline at end of file
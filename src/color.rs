@@ -0,0 +1,77 @@
+use std::str::FromStr;
+
+use crate::vec3::Color;
+
+// All rendering happens on linear Rec.709 / sRGB primaries. These helpers convert to and from
+// the non-linear sRGB encoding used by 8-bit images, both for output and for input textures.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn decode(&self, value: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb => srgb_to_linear(value),
+            ColorSpace::Linear => value,
+        }
+    }
+
+    pub fn encode(&self, value: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb => linear_to_srgb(value),
+            ColorSpace::Linear => value,
+        }
+    }
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "srgb" => Ok(ColorSpace::Srgb),
+            "linear" => Ok(ColorSpace::Linear),
+            _ => Err(format!("Unknown color space '{}'", s)),
+        }
+    }
+}
+
+// The sRGB OETF (IEC 61966-2-1), linear light to encoded value
+pub fn linear_to_srgb(v: f64) -> f64 {
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// The inverse of linear_to_srgb, encoded value to linear light
+pub fn srgb_to_linear(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// CIE xy chromaticities of the sRGB red, green and blue primaries and D65 white point
+pub const SRGB_PRIMARIES: [(f32, f32); 4] = [
+    (0.64, 0.33),
+    (0.30, 0.60),
+    (0.15, 0.06),
+    (0.3127, 0.3290),
+];
+
+impl Color {
+    pub fn from_rgb8(rgb: [u8; 3], space: ColorSpace) -> Color {
+        let [r, g, b] = rgb.map(|c| space.decode(c as f64 / 255.0));
+        Color::new(r, g, b)
+    }
+
+    pub fn to_srgb(&self) -> Color {
+        Color::new(linear_to_srgb(self.x()), linear_to_srgb(self.y()), linear_to_srgb(self.z()))
+    }
+}
@@ -0,0 +1,108 @@
+use rayon::prelude::*;
+
+use crate::vec3::{Color, Vec3};
+
+// Joint (cross) bilateral filter guided by the albedo, normal and depth AOVs. Neighbouring pixels
+// are averaged only when they look like the same surface, so noise is smoothed away while
+// texture and geometry edges survive. The lighting is filtered with the albedo divided out and
+// multiplied back in afterwards, which keeps texture detail out of the blur entirely.
+pub struct Denoiser {
+    radius: i64,
+    sigma_spatial: f64,
+    sigma_albedo: f64,
+    sigma_normal: f64,
+    sigma_depth: f64,
+}
+
+// Guide buffers for one frame, all laid out like the image being filtered
+pub struct Guides<'a> {
+    pub albedo: &'a [Color],
+    pub normal: &'a [Vec3],
+    pub depth: &'a [Color],
+}
+
+impl Denoiser {
+
+    pub fn new(radius: u32) -> Self {
+        Denoiser {
+            radius: radius as i64,
+            sigma_spatial: radius as f64 / 2.0,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.25,
+            sigma_depth: 0.1,
+        }
+    }
+
+    pub fn denoise(&self, width: u32, height: u32, pixels: &[Color], guides: &Guides) -> Vec<Color> {
+        // A radius of 0 has no neighbours to average, and would make every spatial weight 0/0
+        if self.radius == 0 {
+            return pixels.to_vec()
+        }
+
+        let irradiance: Vec<Color> = pixels.iter()
+                                           .zip(guides.albedo)
+                                           .map(|(c, a)| demodulate(*c, *a))
+                                           .collect();
+
+        (0..pixels.len()).into_par_iter()
+                         .map(|index| self.filter_pixel(index, width as i64, height as i64, &irradiance, guides))
+                         .collect()
+    }
+
+    fn filter_pixel(&self, index: usize, w: i64, h: i64, irradiance: &[Color], guides: &Guides) -> Color {
+        let (x, y) = (index as i64 % w, index as i64 / w);
+        let mut sum = Color::zero();
+        let mut weight_sum = 0.0;
+
+        for dy in -self.radius..=self.radius {
+            for dx in -self.radius..=self.radius {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                    continue
+                }
+                let other = (ny * w + nx) as usize;
+                let weight = self.weight(index, other, (dx * dx + dy * dy) as f64, guides);
+                sum = sum + weight * irradiance[other];
+                weight_sum += weight;
+            }
+        }
+
+        (sum / weight_sum) * guides.albedo[index]
+    }
+
+    fn weight(&self, center: usize, other: usize, distance_squared: f64, guides: &Guides) -> f64 {
+        let albedo = (guides.albedo[center] - guides.albedo[other]).length_squared();
+        let normal = (guides.normal[center] - guides.normal[other]).length_squared();
+
+        // Depth differences are relative, so distant surfaces aren't held to a stricter standard
+        let (d0, d1) = (guides.depth[center].x(), guides.depth[other].x());
+        let depth = if d0 > 0.0 { ((d0 - d1) / d0).powi(2) } else { (d1 > 0.0) as u8 as f64 };
+
+        f64::exp(-distance_squared / (2.0 * self.sigma_spatial.powi(2))
+                 - albedo / (2.0 * self.sigma_albedo.powi(2))
+                 - normal / (2.0 * self.sigma_normal.powi(2))
+                 - depth / (2.0 * self.sigma_depth.powi(2)))
+    }
+}
+
+fn demodulate(color: Color, albedo: Color) -> Color {
+    let divide = |c: f64, a: f64| if a > 1e-3 { c / a } else { 0.0 };
+    Color::new(divide(color.x(), albedo.x()), divide(color.y(), albedo.y()), divide(color.z(), albedo.z()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_radius_leaves_the_image_alone() {
+        let pixels = [Color::new(0.1, 0.2, 0.3), Color::new(1, 2, 3)];
+        let guides = Guides {
+            albedo: &[Color::new(0.5, 0.5, 0.5); 2],
+            normal: &[Vec3::new(0, 1, 0); 2],
+            depth: &[Color::new(1, 1, 1); 2],
+        };
+        assert_eq!(Denoiser::new(0).denoise(2, 1, &pixels, &guides), pixels);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,266 @@
+use std::str::FromStr;
+
+use rayon::prelude::*;
+
+use crate::filter::Filter;
+use crate::vec3::{Color, Point3, Vec3};
+
+// Arbitrary output variables: extra passes taken from the first hit of each camera ray
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    ObjectId,
+    MaterialId,
+}
+
+impl Aov {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Position, Aov::ObjectId, Aov::MaterialId]
+            .into_iter()
+            .find(|aov| aov.name() == s.to_ascii_lowercase())
+            .ok_or(format!("Unknown AOV '{}'", s))
+    }
+}
+
+// Per-pixel AOV accumulator. Albedo and normal are summed over `samples` samples like radiance;
+// depth, position and the IDs are those of the nearest hit seen so far. IDs are 1-based with 0
+// meaning the ray escaped. AOVs keep their own sample count since they are only traced when
+// asked for.
+#[derive(Debug, Copy, Clone)]
+pub struct AovSample {
+    pub samples: u64,
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f64,
+    pub position: Point3,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl AovSample {
+    pub fn miss(background: Color) -> Self {
+        AovSample {
+            samples: 1,
+            albedo: background,
+            normal: Vec3::zero(),
+            depth: f64::INFINITY,
+            position: Point3::zero(),
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+
+    pub fn empty() -> Self {
+        AovSample {
+            samples: 0,
+            ..AovSample::miss(Color::zero())
+        }
+    }
+
+    pub fn add(&mut self, sample: &AovSample) {
+        self.samples += sample.samples;
+        self.albedo = self.albedo + sample.albedo;
+        self.normal = self.normal + sample.normal;
+        if sample.depth < self.depth {
+            self.depth = sample.depth;
+            self.position = sample.position;
+            self.object_id = sample.object_id;
+            self.material_id = sample.material_id;
+        }
+    }
+}
+
+// Filter-weighted radiance splatted by samples into a band of rows, merged into the film
+// once the band is done
+pub struct Splats {
+    width: u32,
+    height: u32,
+    start_row: i64,
+    rows: i64,
+    sum: Vec<Color>,
+    weight: Vec<f64>,
+}
+
+impl Splats {
+
+    // Covers rows start_row..start_row + rows of a film, which may run past its edges
+    pub fn new(width: u32, height: u32, start_row: i64, rows: i64) -> Self {
+        let len = (width as i64 * rows) as usize;
+        Splats {
+            width,
+            height,
+            start_row,
+            rows,
+            sum: vec![Color::zero(); len],
+            weight: vec![0.0; len],
+        }
+    }
+
+    // Splats a sample taken at film position (x, y), measured in pixels, into every pixel
+    // within the filter's radius
+    pub fn add(&mut self, x: f64, y: f64, color: Color, filter: &Filter) {
+        let r = filter.radius();
+        let x0 = ((x - 0.5 - r).ceil() as i64).max(0);
+        let x1 = ((x - 0.5 + r).floor() as i64).min(self.width as i64 - 1);
+        let y0 = ((y - 0.5 - r).ceil() as i64).max(0).max(self.start_row);
+        let y1 = ((y - 0.5 + r).floor() as i64).min(self.height as i64 - 1).min(self.start_row + self.rows - 1);
+
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let weight = filter.evaluate(px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                let index = ((py - self.start_row) * self.width as i64 + px) as usize;
+                self.sum[index] = self.sum[index] + weight * color;
+                self.weight[index] += weight;
+            }
+        }
+    }
+}
+
+// Accumulates radiance samples per pixel. Rows are stored in render order, bottom to top, so
+// pixel (i, j) lives at j * width + i. Radiance is stored as a filter-weighted sum along with
+// the total filter weight, while sample counts and AOVs belong to the pixel a sample was
+// taken in.
+pub struct Film {
+    width: u32,
+    height: u32,
+    sum: Vec<Color>,
+    weight: Vec<f64>,
+    samples: Vec<u64>,
+    aovs: Vec<AovSample>,
+}
+
+impl Film {
+
+    pub fn new(width: u32, height: u32) -> Self {
+        let len = (width * height) as usize;
+        Film {
+            width,
+            height,
+            sum: vec![Color::zero(); len],
+            weight: vec![0.0; len],
+            samples: vec![0; len],
+            aovs: vec![AovSample::empty(); len],
+        }
+    }
+
+    pub fn from_parts(width: u32, height: u32, sum: Vec<Color>, weight: Vec<f64>, samples: Vec<u64>, aovs: Vec<AovSample>) -> Self {
+        assert_eq!(sum.len(), (width * height) as usize);
+        assert_eq!(weight.len(), sum.len());
+        assert_eq!(samples.len(), sum.len());
+        assert_eq!(aovs.len(), sum.len());
+        Film {
+            width,
+            height,
+            sum,
+            weight,
+            samples,
+            aovs,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn sums(&self) -> &[Color] {
+        &self.sum
+    }
+
+    pub fn weights(&self) -> &[f64] {
+        &self.weight
+    }
+
+    pub fn sample_counts(&self) -> &[u64] {
+        &self.samples
+    }
+
+    pub fn aov_samples(&self) -> &[AovSample] {
+        &self.aovs
+    }
+
+    // Mutable access to the sample counts and AOVs of bands of `rows` rows, in render order
+    pub fn bands_mut(&mut self, rows: u32) -> impl IndexedParallelIterator<Item = (&mut [u64], &mut [AovSample])> {
+        let len = (self.width * rows) as usize;
+        self.samples.par_chunks_mut(len)
+                    .zip(self.aovs.par_chunks_mut(len))
+    }
+
+    pub fn add_splats(&mut self, splats: &Splats) {
+        let w = self.width as i64;
+        for (offset, (sum, weight)) in splats.sum.iter().zip(&splats.weight).enumerate() {
+            let index = splats.start_row * w + offset as i64;
+            if (0..self.sum.len() as i64).contains(&index) {
+                self.sum[index as usize] = self.sum[index as usize] + *sum;
+                self.weight[index as usize] += weight;
+            }
+        }
+    }
+
+    // Fewest samples taken by any pixel. A film without pixels has nothing left to sample, so
+    // it counts as done.
+    pub fn samples_taken(&self) -> u64 {
+        self.samples.iter().copied().min().unwrap_or(u64::MAX)
+    }
+
+    // Filtered radiance per pixel, with rows flipped to run top to bottom
+    pub fn pixels(&self) -> Vec<Color> {
+        self.top_down(|index| {
+            let weight = self.weight[index];
+            if weight.abs() < 1e-12 { Color::zero() } else { self.sum[index] / weight }
+        })
+    }
+
+    // One AOV as an image, with rows flipped to run top to bottom. Depth is 0 where nothing
+    // was hit, and IDs are written as plain numbers in every channel.
+    pub fn aov_pixels(&self, aov: Aov) -> Vec<Color> {
+        self.top_down(|index| {
+            let sample = &self.aovs[index];
+            let depth = if sample.depth.is_finite() { sample.depth } else { 0.0 };
+            match aov {
+                Aov::Albedo => average(sample.albedo, sample.samples),
+                Aov::Normal => average(sample.normal, sample.samples),
+                Aov::Depth => Color::new(depth, depth, depth),
+                Aov::Position => sample.position,
+                Aov::ObjectId => Color::new(sample.object_id, sample.object_id, sample.object_id),
+                Aov::MaterialId => Color::new(sample.material_id, sample.material_id, sample.material_id),
+            }
+        })
+    }
+
+    fn top_down<F: Fn(usize) -> Color>(&self, pixel: F) -> Vec<Color> {
+        let w = self.width as usize;
+        (0..self.height as usize).rev()
+                                 .flat_map(|j| (0..w).map(move |i| j * w + i))
+                                 .map(pixel)
+                                 .collect()
+    }
+}
+
+fn average(sum: Color, samples: u64) -> Color {
+    match samples {
+        0 => Color::zero(),
+        n => sum / n as f64,
+    }
+}
@@ -0,0 +1,121 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 2.0,
+        }
+    }
+
+    pub fn from_index(index: u32) -> Option<FilterKind> {
+        [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos]
+            .get(index as usize)
+            .copied()
+    }
+
+    pub fn index(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        (0..)
+            .map_while(FilterKind::from_index)
+            .find(|kind| kind.name() == s.to_ascii_lowercase())
+            .ok_or(format!("Unknown filter '{}'", s))
+    }
+}
+
+// Pixel reconstruction filter. Every sample is splatted into all pixels whose centers lie within
+// `radius` pixels of it, weighted by the filter, and each pixel is normalised by the total weight
+// it received.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Filter {
+    kind: FilterKind,
+    radius: f64,
+}
+
+impl Filter {
+
+    pub fn new(kind: FilterKind, radius: f64) -> Self {
+        Filter { kind, radius }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    // Weight of a sample at offset (dx, dy) pixels from a pixel center
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        let r = self.radius;
+        if x > r {
+            return 0.0
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => r - x,
+            FilterKind::Gaussian => {
+                // Shifted down so the filter reaches zero at its radius
+                let sigma = r / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(r)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+        }
+    }
+}
+
+// Mitchell-Netravali cubic with B = C = 1/3, defined over [0, 2]
+fn mitchell(x: f64) -> f64 {
+    let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2) + (6.0 - 2.0 * b)) / 6.0
+    } else {
+        ((-b - 6.0 * c) * x.powi(3) + (6.0 * b + 30.0 * c) * x.powi(2) + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0
+    }
+    (PI * x).sin() / (PI * x)
+}
@@ -3,28 +3,56 @@ pub mod vec3;
 pub mod shapes;
 pub mod camera;
 pub mod materials;
-
+pub mod cli;
+pub mod output;
+pub mod tonemap;
+pub mod color;
+pub mod film;
+pub mod checkpoint;
+pub mod render;
+pub mod denoise;
+pub mod filter;
+pub mod microfacet;
+pub mod spectrum;
+pub mod textures;
+pub mod simd;
+pub mod bench;
+
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use materials::Scatter;
-use rayon::prelude::*;
-use indicatif::{ParallelProgressIterator, ProgressStyle};
-use image::ImageBuffer;
+use indicatif::{ProgressBar, ProgressStyle};
 use piston_window::EventLoop;
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 use crate::materials::{Lambertian, Metal, Dialectric};
+use crate::spectrum::Ior;
 use crate::vec3::{Point3, Vec3, Color};
-use crate::ray::Ray;
 use crate::shapes::{Hit, HittableList, sphere::Sphere};
-use crate::camera::Camera;
+use crate::shapes::packet::PacketList;
+use crate::camera::{Camera, Project, Projection};
+use crate::camera::animation::{CameraKey, CameraPath};
+use crate::camera::distortion::LensProfile;
+use crate::camera::exposure::{f_number, Exposure};
+use crate::camera::projections::{CubeMap, Equirectangular, Fisheye, FisheyeMapping, Frame, Orthographic};
+use crate::camera::stereo::{Stereo, StereoRig};
+use crate::cli::Options;
+use crate::film::{Aov, Film};
+use crate::denoise::{Denoiser, Guides};
+use crate::filter::Filter;
+use crate::render::{MaterialIds, RenderSettings};
+use crate::output::OutputSettings;
+use crate::tonemap::ToneMap;
 
 
 
 const ASPECT_RATIO: f64 = 3.0/2.0;
 const IMAGE_WIDTH: u32 = 200;
-const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u32;
 const SAMPLES_PER_PIXEL: u64 = 100;
+const SAMPLES_PER_PASS: u64 = 10;
 const MAX_DEPTH: i32 = 50;
 
 enum DebugSaving {
@@ -35,29 +63,9 @@ enum DebugSaving {
 
 const SAVE_IMAGE: DebugSaving = DebugSaving::Save;
 
-fn ray_color(r: &Ray, world: &HittableList, depth: i32) -> Color { 
-
-    if depth <= 0 {
-        return Color::zero()
-    }
-
-    let res = world.hit(r, 0.0001, f64::INFINITY);
-
-    if let Some(shape) = res {
-        let scatter = shape.get_mat().scatter(r, &shape);
-        if let Some((att, scat)) = scatter {
-            return att * ray_color(&scat, world, depth-1)
-        }
-        return Color::new(0,0,0);
-    }
-
-    let unit_direction: Vec3 = r.direction().unit_vector();
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    (1.0 - t) * Color::new(1, 1, 1) + t*Color::new(0.5, 0.7, 1.0)
-}
-
-fn random_scene() -> HittableList {
-    let mut rng = thread_rng();
+// In spectral mode the large glass sphere is BK7, so it disperses light
+fn random_scene(seed: u64, spectral: bool) -> HittableList {
+    let mut rng = StdRng::seed_from_u64(seed);
 
     let mut world = HittableList::new();
     let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
@@ -72,11 +80,11 @@ fn random_scene() -> HittableList {
             if (center - Point3::new(4, 0.2, 0)).length() > 0.9 {
                 let sphere_material: Arc<dyn Scatter> = match rng.gen() {
                     x if (0.0..=0.8).contains(&x) => {
-                        let albedo = Color::random() * Color::random();
+                        let albedo = Color::random_with(&mut rng) * Color::random_with(&mut rng);
                         Arc::new(Lambertian::new(albedo))
                     }
                     x if (0.8..=0.95).contains(&x) => {
-                        let albedo = Color::random_in_range(0.5, 1);
+                        let albedo = Color::random_in_range_with(&mut rng, 0.5, 1);
                         let fuzz = rng.gen_range(0.0..0.5);
                         Arc::new(Metal::new(albedo, fuzz))
                     }
@@ -89,7 +97,7 @@ fn random_scene() -> HittableList {
         }
     }
 
-    let material1 = Arc::new(Dialectric::new(1.5));
+    let material1 = Arc::new(if spectral { Dialectric::dispersive(Ior::bk7()) } else { Dialectric::new(1.5) });
     world.push(Box::new(Sphere::new(Point3::new(0, 1, 0), 1.0, material1)));
 
     let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
@@ -101,56 +109,240 @@ fn random_scene() -> HittableList {
     world
 }
 
+// Builds the camera for one set of camera parameters. With an autofocus point, the perspective
+// camera focuses on whatever is visible at that point of the image.
+fn camera(settings: &RenderSettings, key: &CameraKey, world: &dyn Hit) -> Box<dyn Project> {
+    let v_up = Vec3::new(0, 1, 0);
+    let convergence = settings.stereo.map_or(key.focus_dist, |rig| rig.convergence);
+    let frame = |eye_offset| Frame::new(key.look_from, key.look_at, v_up).with_eye_offset(eye_offset);
+    let eye = |eye_offset: f64| -> Box<dyn Project> {
+        match settings.projection {
+            Projection::Perspective => {
+                let mut cam = Camera::new(
+                    key.look_from,
+                    key.look_at,
+                    v_up,
+                    key.v_fov, 
+                    ASPECT_RATIO,
+                    key.aperture,
+                    key.focus_dist
+                );
+                if let Some((x, y)) = settings.autofocus {
+                    cam = cam.autofocus(world, x, 1.0 - y);
+                }
+                if let Some(lens) = settings.lens {
+                    cam = cam.with_lens_profile(lens);
+                }
+                Box::new(cam.with_eye_offset(eye_offset, convergence))
+            }
+            Projection::Orthographic => Box::new(Orthographic::new(frame(eye_offset), 4.0, ASPECT_RATIO)),
+            Projection::Fisheye => Box::new(Fisheye::new(frame(eye_offset), 180.0, ASPECT_RATIO, FisheyeMapping::Equidistant)),
+            Projection::Equisolid => Box::new(Fisheye::new(frame(eye_offset), 180.0, ASPECT_RATIO, FisheyeMapping::Equisolid)),
+            Projection::Equirectangular => Box::new(Equirectangular::new(frame(0.0)).with_eye_offset(eye_offset)),
+            Projection::CubeMap => Box::new(CubeMap::new(frame(eye_offset))),
+        }
+    };
+    match settings.stereo {
+        Some(rig) => Box::new(Stereo::new(eye(-rig.interocular / 2.0), eye(rig.interocular / 2.0), rig.layout)),
+        None => eye(0.0),
+    }
+}
+
+fn progress_bar(settings: &RenderSettings, film: &Film) -> ProgressBar {
+    let style = ProgressStyle::with_template("[{elapsed} elapsed] [Expected in: {eta}] {wide_bar:40.cyan/blue} {pos:>7}/{len:7}").unwrap();
+    let remaining = settings.samples_per_pixel.saturating_sub(film.samples_taken());
+    ProgressBar::new(remaining.div_ceil(SAMPLES_PER_PASS) * film.height() as u64)
+        .with_style(style)
+}
+
+// Renders passes of a few samples per pixel until the film is done, saving a checkpoint to the
+// given path whenever the interval has passed
+fn render_passes(film: &mut Film, cam: &dyn Project, world: &dyn Hit, settings: &RenderSettings,
+                 aov_ids: Option<&MaterialIds>, bar: &ProgressBar, checkpoint: Option<(&Path, Duration)>) {
+    let mut last_checkpoint = Instant::now();
+
+    while film.samples_taken() < settings.samples_per_pixel {
+        let samples = SAMPLES_PER_PASS.min(settings.samples_per_pixel - film.samples_taken());
+        render::render_pass(film, cam, world, settings, aov_ids, samples, bar);
+
+        if let Some((path, interval)) = checkpoint {
+            if last_checkpoint.elapsed() >= interval {
+                // A failed save shouldn't cost the render it's meant to protect, so try again later
+                if let Err(e) = checkpoint::save(path, settings, film) {
+                    bar.suspend(|| eprintln!("Could not save checkpoint {}: {}", path.display(), e));
+                }
+                last_checkpoint = Instant::now();
+            }
+        }
+    }
+    bar.finish();
+}
+
+// Linear framebuffer, rows running top to bottom, denoised if asked for and then exposed
+// through the camera taking the picture
+fn final_pixels(opts: &Options, film: &Film, key: &CameraKey) -> Vec<Color> {
+    let mut pixels = film.pixels();
+    if opts.denoise {
+        let guides = Guides {
+            albedo: &film.aov_pixels(Aov::Albedo),
+            normal: &film.aov_pixels(Aov::Normal),
+            depth: &film.aov_pixels(Aov::Depth),
+        };
+        pixels = Denoiser::new(opts.denoise_radius).denoise(film.width(), film.height(), &pixels, &guides);
+    }
+
+    let scale = match (opts.shutter, opts.iso) {
+        (None, None) => 1.0,
+        (shutter, iso) => Exposure {
+            f_number: f_number(key.v_fov, key.aperture),
+            shutter: shutter.unwrap_or(1.0 / 125.0),
+            iso: iso.unwrap_or(100.0),
+        }.scale(),
+    };
+    pixels.iter().map(|c| opts.response.apply(*c * scale)).collect()
+}
+
+fn save_outputs(opts: &Options, output: &Path, film: &Film, pixels: &[Color]) {
+    output::save(output, film.width(), film.height(), pixels, &opts.output_settings()).unwrap();
+
+    // Extra passes hold data rather than radiance, so they skip tone mapping
+    let aov_settings = OutputSettings { tone_map: ToneMap::identity(), ..opts.output_settings() };
+    for aov in &opts.aovs {
+        let path = output::aov_path(output, aov.name());
+        output::save(&path, film.width(), film.height(), &film.aov_pixels(*aov), &aov_settings).unwrap();
+    }
+}
+
 fn main() {
+
+    let opts = Options::from_args();
+
+    let (settings, mut film) = match &opts.resume {
+        Some(path) => {
+            let (mut settings, film) = checkpoint::load(path)
+                .unwrap_or_else(|e| panic!("Could not load checkpoint {}: {}", path.display(), e));
+            if let Some(samples) = opts.samples {
+                settings.samples_per_pixel = samples;
+            }
+            println!("Resuming from {} at {} samples per pixel", path.display(), film.samples_taken());
+            (settings, film)
+        }
+        None => {
+            let width = opts.width.unwrap_or(IMAGE_WIDTH);
+            let stereo = opts.stereo.map(|layout| StereoRig {
+                layout,
+                interocular: opts.interocular,
+                convergence: opts.convergence,
+            });
+            // Each eye of a stereo pair keeps the usual aspect ratio
+            let aspect_ratio = stereo.map_or(ASPECT_RATIO, |rig| rig.layout.aspect_ratio(ASPECT_RATIO));
+            let height = (width as f64 / aspect_ratio) as u32;
+            if height < 2 {
+                cli::exit_with_usage(&format!("A {} pixel wide image is less than 2 pixels tall", width));
+            }
+            if let Some(crop) = opts.crop {
+                let fits = |start: u32, size: u32, limit| start.checked_add(size).is_some_and(|end| end <= limit);
+                if !fits(crop.x, crop.width, width) || !fits(crop.y, crop.height, height) {
+                    cli::exit_with_usage(&format!("The crop window {},{},{},{} does not fit in the {}x{} image",
+                                                  crop.x, crop.y, crop.width, crop.height, width, height));
+                }
+            }
+            let settings = RenderSettings {
+                width,
+                height,
+                samples_per_pixel: opts.samples.unwrap_or(SAMPLES_PER_PIXEL),
+                max_depth: MAX_DEPTH,
+                seed: opts.seed.unwrap_or_else(|| thread_rng().gen()),
+                filter: Filter::new(opts.filter, opts.filter_radius.unwrap_or(opts.filter.default_radius())),
+                spectral: opts.spectral,
+                projection: opts.projection,
+                stereo,
+                crop: opts.crop,
+                lens: opts.lens_profile.as_ref().map(|path| {
+                    LensProfile::load(path)
+                        .unwrap_or_else(|e| panic!("Could not load lens profile {}: {}", path.display(), e))
+                }),
+                autofocus: opts.autofocus,
+            };
+            let (film_width, film_height) = settings.film_size();
+            let film = Film::new(film_width, film_height);
+            (settings, film)
+        }
+    };
+    // Keep checkpointing into the file we resumed from unless told otherwise
+    let checkpoint_path = opts.checkpoint.as_ref().or(opts.resume.as_ref());
+    let checkpoint_interval = Duration::from_secs(opts.checkpoint_interval);
     
-    let world = random_scene();
-
-    let look_from = Point3::new(13, 2, 3);
-    let look_at = Point3::new(0, 0, 0);
-    let cam: Camera = Camera::new(
-        look_from,
-        look_at,
-        Vec3::new(0, 1, 0),
-        20.0, 
-        ASPECT_RATIO,
-        0.1,
-        10.0
-    );
-
-    let style = ProgressStyle::with_template("[{elapsed} elapsed] [Expected in: {eta}] {wide_bar:40.cyan/blue} {pos:>  7}/{len:7}").unwrap();
-//    Rendering
-    let pixels: Vec<u8> = (0..IMAGE_HEIGHT)
-                .into_par_iter()
-                .progress_with_style(style)
-                .flat_map_iter(|j| (0..IMAGE_WIDTH).map(move |i| (i, j)))
-                .flat_map_iter(|(i, j)| {
-                    let mut pixel_color: Color = Color::zero();
-                    let mut rng = thread_rng();
-
-                    for _ in 0..SAMPLES_PER_PIXEL {
-                        let u = (i as f64 + rng.gen::<f64>()) / ((IMAGE_WIDTH-1)  as f64);
-                        let v = (j as f64 + rng.gen::<f64>()) / ((IMAGE_HEIGHT-1) as f64);
-
-                        let r = cam.get_ray(u, v);
-                        pixel_color = pixel_color + ray_color(&r, &world, MAX_DEPTH);
-                    }
-                    pixel_color.to_rgba(255, SAMPLES_PER_PIXEL)
-                })
-                .collect();
-    
-    let pixels = pixels.chunks(4 * IMAGE_WIDTH as usize) // times 4 due to R G B and A channels for each pixel
-                       .rev()                   
-                       .flatten()
-                       .copied()
-                       .collect();
+    let world = PacketList::new(random_scene(settings.seed, settings.spectral));
+
+    let material_ids = MaterialIds::new(world.objects());
+    let aov_ids = (opts.denoise || !opts.aovs.is_empty()).then_some(&material_ids);
+
+    let key = CameraKey {
+        time: 0.0,
+        look_from: Point3::new(13, 2, 3),
+        look_at: Point3::new(0, 0, 0),
+        v_fov: 20.0,
+        focus_dist: 10.0,
+        aperture: 0.1,
+    };
+
+    if opts.bench {
+        let cam = camera(&settings, &key, &world);
+        bench::run(&world, cam.as_ref(), &settings);
+        return
+    }
+
+    if let Some((x, y)) = opts.debug_pixel {
+        if x >= settings.width || y >= settings.height {
+            cli::exit_with_usage(&format!("Pixel {},{} is outside the {}x{} image", x, y, settings.width, settings.height));
+        }
+        let cam = camera(&settings, &key, &world);
+        render::debug_pixel(cam.as_ref(), &world, &settings, x, y, opts.samples.unwrap_or(1));
+        return
+    }
+
+    // Image sequence mode: a turntable around the scene, one file per frame
+    if let Some(frames) = opts.frames {
+        let path = CameraPath::orbit(key, 8);
+        for frame in 0..frames {
+            let key = path.at(frame as f64 / frames as f64);
+            let cam = camera(&settings, &key, &world);
+            let (film_width, film_height) = settings.film_size();
+            let mut film = Film::new(film_width, film_height);
+            println!("Frame {}/{}", frame + 1, frames);
+            let bar = progress_bar(&settings, &film);
+            render_passes(&mut film, cam.as_ref(), &world, &settings, aov_ids, &bar, None);
+
+            let pixels = final_pixels(&opts, &film, &key);
+            save_outputs(&opts, &output::frame_path(&opts.output, frame + 1), &film, &pixels);
+        }
+        println!("\nDone.");
+        return
+    }
+
+    let cam = camera(&settings, &key, &world);
+
+//    Rendering, in passes of a few samples per pixel so progress can be checkpointed
+    let bar = progress_bar(&settings, &film);
+    let checkpoint = checkpoint_path.map(|path| (path.as_path(), checkpoint_interval));
+    render_passes(&mut film, cam.as_ref(), &world, &settings, aov_ids, &bar, checkpoint);
+
+    if let Some(path) = checkpoint_path {
+        checkpoint::save(path, &settings, &film)
+            .unwrap_or_else(|e| panic!("Could not save checkpoint {}: {}", path.display(), e));
+    }
     
-    let image_buffer = ImageBuffer::from_vec(IMAGE_WIDTH, IMAGE_HEIGHT, pixels).unwrap();
+    let pixels = final_pixels(&opts, &film, &key);
+    let output_settings = opts.output_settings();
+    let save_outputs = || save_outputs(&opts, &opts.output, &film, &pixels);
+    let image_buffer = output::to_rgba_image(film.width(), film.height(), &pixels, &output_settings.tone_map);
     
     println!("\nDone.");
 
     // Drawing preview window
     
-    let mut window: piston_window::PistonWindow = piston_window::WindowSettings::new("Scene", [IMAGE_WIDTH, IMAGE_HEIGHT])
+    let mut window: piston_window::PistonWindow = piston_window::WindowSettings::new("Scene", [film.width(), film.height()])
         .exit_on_esc(true)
         .build()
         .unwrap_or_else(|_e| { panic!("Could not create window!")});
@@ -174,7 +366,7 @@ fn main() {
     // Save or discard image
     
     match SAVE_IMAGE {
-        DebugSaving::Save => image_buffer.save("image.png").unwrap(),
+        DebugSaving::Save => save_outputs(),
         DebugSaving::Quit => (),
         DebugSaving::Choose => {
         
@@ -192,8 +384,8 @@ fn main() {
                 
                 
                 if ["s", "S"].contains(&san_input) {
-                    image_buffer.save("image.png").unwrap();
-                    println!("image.png saved to working directory");
+                    save_outputs();
+                    println!("{} saved", opts.output.display());
                     valid = true;
                 } else if ["q", "Q"].contains(&san_input) {
                     valid = true;
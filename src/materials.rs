@@ -1,24 +1,55 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
 use rand::{thread_rng, Rng};
 
 use crate::{ray::Ray, vec3::{Color, Vec3}, shapes::HitRecord};
+use crate::spectrum::Ior;
+use crate::textures::{SolidColor, Texture};
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, fresnel_schlick, Ggx, Onb};
 
 pub trait Scatter :Send + Sync{
+    // Short description for debug output
+    fn name(&self) -> &'static str;
+
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    // Overall surface color at the hit, independent of lighting. Used for the albedo AOV.
+    fn albedo(&self, rec: &HitRecord) -> Color;
+
+    // Normal the material actually shades with, for the normal AOV. Normal and bump maps
+    // replace the hit's own.
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        rec.get_normal()
+    }
+}
+
+fn texture_at(texture: &dyn Texture, rec: &HitRecord) -> Color {
+    let (u, v) = rec.get_uv();
+    texture.value(u, v, &rec.get_p())
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(a: Color) -> Self {
+        Lambertian::textured(Arc::new(SolidColor(a)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
         Lambertian {
-            albedo: a,
+            albedo,
         }
     }
 }
 
 impl Scatter for Lambertian {            
+    fn name(&self) -> &'static str {
+        "lambertian"
+    }
+
     fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let mut scatter_direction = rec.get_normal() + Vec3::random_unit_vector();
 
@@ -26,10 +57,84 @@ impl Scatter for Lambertian {
             scatter_direction = rec.get_normal();
         }
         let scattered = Ray::new(rec.get_p(), scatter_direction);
-        Some((self.albedo, scattered))
+        Some((texture_at(self.albedo.as_ref(), rec), scattered))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        texture_at(self.albedo.as_ref(), rec)
+    }
+}
+
+
+// Oren-Nayar rough diffuse (qualitative model). Microfacets are Lambertian V-grooves whose
+// slopes have a standard deviation of `sigma` radians, which brightens backscatter and flattens
+// rough surfaces like clay. With sigma = 0 it is exactly Lambertian. Directions are cosine
+// sampled, leaving only the Oren-Nayar factor in the weight.
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Color, sigma: f64) -> Self {
+        OrenNayar::textured(Arc::new(SolidColor(albedo)), sigma)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, sigma: f64) -> Self {
+        let sigma2 = sigma * sigma;
+        OrenNayar {
+            albedo,
+            a: 1.0 - 0.5 * sigma2 / (sigma2 + 0.33),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    // Oren-Nayar over Lambertian reflectance for local directions around +z
+    fn factor(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let (cos_i, cos_o) = (wi.z().clamp(0.0, 1.0), wo.z().clamp(0.0, 1.0));
+        let (sin_i, sin_o) = ((1.0 - cos_i * cos_i).sqrt(), (1.0 - cos_o * cos_o).sqrt());
+
+        // cos(phi_i - phi_o) from the projections onto the tangent plane
+        let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+            ((wi.x() * wo.x() + wi.y() * wo.y()) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+        // sin(alpha) tan(beta), with alpha the larger and beta the smaller polar angle
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i)
+        } else {
+            (sin_i, sin_o / cos_o.max(1e-4))
+        };
+
+        self.a + self.b * cos_phi * sin_alpha * tan_beta
     }
 }
 
+impl Scatter for OrenNayar {
+    fn name(&self) -> &'static str {
+        "oren-nayar"
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Onb::new(rec.get_normal());
+        let wo = frame.to_local(-r_in.direction().unit_vector());
+
+        let mut wi = Vec3::new(0, 0, 1) + Vec3::random_unit_vector();
+        if wi.is_near_zero() {
+            wi = Vec3::new(0, 0, 1);
+        }
+        let wi = wi.unit_vector();
+
+        let factor = self.factor(&wo, &wi);
+        Some((factor * texture_at(self.albedo.as_ref(), rec), Ray::new(rec.get_p(), frame.to_world(wi))))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        texture_at(self.albedo.as_ref(), rec)
+    }
+}
 
 pub struct Metal {
     albedo: Color,
@@ -46,6 +151,10 @@ impl Metal {
 }
 
 impl Scatter for Metal {
+    fn name(&self) -> &'static str {
+        "metal"
+    }
+
             
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let reflected = r_in.direction().reflect(&rec.get_normal()).unit_vector();
@@ -59,21 +168,134 @@ impl Scatter for Metal {
         let attenuation = self.albedo;
         Some((attenuation, scattered))
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+// Fresnel term for conductors: Schlick's approximation from the color at normal incidence, or
+// the exact conductor equations from a complex index of refraction eta + ik per channel
+pub enum ConductorFresnel {
+    Schlick(Color),
+    Complex { eta: Color, k: Color },
+}
+
+impl ConductorFresnel {
+    fn evaluate(&self, cos_theta: f64) -> Color {
+        match self {
+            ConductorFresnel::Schlick(f0) => fresnel_schlick(*f0, cos_theta),
+            ConductorFresnel::Complex { eta, k } => fresnel_conductor(cos_theta, *eta, *k),
+        }
+    }
+}
+
+// Microfacet metal using the GGX distribution with Smith masking-shadowing. Reflections are
+// importance sampled from the visible normals, which leaves Fresnel times G2/G1 as the sample
+// weight.
+pub struct Conductor {
+    fresnel: ConductorFresnel,
+    distribution: Ggx,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Conductor {
+            fresnel: ConductorFresnel::Complex { eta, k },
+            distribution: Ggx::new(roughness),
+        }
+    }
+
+    pub fn schlick(f0: Color, roughness: f64) -> Self {
+        Conductor {
+            fresnel: ConductorFresnel::Schlick(f0),
+            distribution: Ggx::new(roughness),
+        }
+    }
+
+    // Measured indices of refraction at roughly 650, 550 and 450 nm
+    pub fn gold(roughness: f64) -> Self {
+        Conductor::new(Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603), roughness)
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Conductor::new(Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142), roughness)
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Conductor::new(Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837), roughness)
+    }
+}
+
+impl Scatter for Conductor {
+    fn name(&self) -> &'static str {
+        "conductor"
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Onb::new(rec.get_normal());
+        let wo = frame.to_local(-r_in.direction().unit_vector());
+
+        let (wi, h, masking) = self.distribution.sample_reflection(wo)?;
+        let attenuation = masking * self.fresnel.evaluate(wo.dot(&h));
+        Some((attenuation, Ray::new(rec.get_p(), frame.to_world(wi))))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.fresnel.evaluate(1.0)
+    }
+}
+
+// Absorption coefficients that leave `tint` behind after light travels `distance` through a medium
+fn absorption_from_tint(tint: Color, distance: f64) -> Color {
+    let coefficient = |c: f64| -c.max(1e-6).ln() / distance;
+    Color::new(coefficient(tint.x()), coefficient(tint.y()), coefficient(tint.z()))
+}
+
+// Beer-Lambert attenuation for a ray that has just crossed an absorbing medium. Shapes are
+// assumed closed, so hitting a back face means the ray travelled through the inside.
+fn medium_transmittance(absorption: Color, r_in: &Ray, rec: &HitRecord) -> Color {
+    if rec.front_face {
+        return Color::new(1,1,1)
+    }
+    let distance = rec.get_t() * r_in.direction().length();
+    Color::new((-absorption.x() * distance).exp(),
+               (-absorption.y() * distance).exp(),
+               (-absorption.z() * distance).exp())
 }
 
 pub struct Dialectric {
-    ir: f64
+    ir: Ior,
+    absorption: Color,
 }
 
 impl Dialectric {
     pub fn new(index_of_refraction: f64) -> Self{
+        Dialectric::dispersive(Ior::Constant(index_of_refraction))
+    }
+
+    // Glass whose index varies with wavelength, splitting light into colors in spectral mode
+    pub fn dispersive(ir: Ior) -> Self {
         Dialectric {
-            ir: index_of_refraction
+            ir,
+            absorption: Color::zero(),
+        }
+    }
+
+    // Colors the glass: light travelling `distance` through it is filtered down to `tint`
+    pub fn with_absorption(self, tint: Color, distance: f64) -> Self {
+        Dialectric {
+            absorption: absorption_from_tint(tint, distance),
+            ..self
         }
     }
 }
 
 impl Scatter for Dialectric {
+    fn name(&self) -> &'static str {
+        "dielectric"
+    }
+
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         fn reflectence(cosine: f64, ref_idx: f64) -> f64 {
             let r0 = ((1.0-ref_idx) / (1.0+ref_idx)).powi(2);
@@ -81,9 +303,10 @@ impl Scatter for Dialectric {
     
         }
 
+        let ir = self.ir.at(r_in.wavelength());
         let refraction_ratio = match rec.front_face {
-            true => 1.0/self.ir,
-            false => self.ir,
+            true => 1.0/ir,
+            false => ir,
         };
 
         let unit_direction = r_in.direction() .unit_vector();
@@ -100,6 +323,569 @@ impl Scatter for Dialectric {
         };
                             
         let scattered = Ray::new(rec.get_p(), direction);
-        Some((Color::new(1,1,1), scattered))
+        Some((medium_transmittance(self.absorption, r_in, rec), scattered))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1,1,1)
+    }
+}
+
+// Frosted glass: a GGX microfacet dielectric that both reflects and transmits through the
+// sampled microfacet normal, choosing between the two by Fresnel reflectance
+pub struct RoughDielectric {
+    ir: Ior,
+    distribution: Ggx,
+    absorption: Color,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f64, roughness: f64) -> Self {
+        RoughDielectric::dispersive(Ior::Constant(index_of_refraction), roughness)
+    }
+
+    pub fn dispersive(ir: Ior, roughness: f64) -> Self {
+        RoughDielectric {
+            ir,
+            distribution: Ggx::new(roughness),
+            absorption: Color::zero(),
+        }
+    }
+
+    // Colors the glass: light travelling `distance` through it is filtered down to `tint`
+    pub fn with_absorption(self, tint: Color, distance: f64) -> Self {
+        RoughDielectric {
+            absorption: absorption_from_tint(tint, distance),
+            ..self
+        }
+    }
+}
+
+impl Scatter for RoughDielectric {
+    fn name(&self) -> &'static str {
+        "rough dielectric"
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        // Ratio of the index on the far side of the boundary to the one the ray is in
+        let ir = self.ir.at(r_in.wavelength());
+        let eta = match rec.front_face {
+            true => ir,
+            false => 1.0/ir,
+        };
+
+        let frame = Onb::new(rec.get_normal());
+        let wo = frame.to_local(-r_in.direction().unit_vector());
+
+        let mut rng = thread_rng();
+        let h = self.distribution.sample_visible_normal(wo, rng.gen(), rng.gen());
+        let cos_i = wo.dot(&h);
+
+        let wi = if rng.gen::<f64>() < fresnel_dielectric(cos_i, eta) {
+            let reflected = (-wo).reflect(&h);
+            if reflected.z() <= 0.0 {
+                return None
+            }
+            reflected
+        } else {
+            let cos_t = (1.0 - (1.0 - cos_i * cos_i) / (eta * eta)).sqrt();
+            let refracted = -wo / eta + (cos_i / eta - cos_t) * h;
+            if refracted.z() >= 0.0 {
+                return None
+            }
+            refracted
+        };
+
+        // Choosing the lobe by Fresnel cancels it out of the weight, leaving G2/G1 from the
+        // visible normal sampling
+        let masking = self.distribution.g(wo, wi) / self.distribution.g1(wo);
+        let attenuation = masking * medium_transmittance(self.absorption, r_in, rec);
+        Some((attenuation, Ray::new(rec.get_p(), frame.to_world(wi))))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1,1,1)
+    }
+}
+
+// Principled, Disney-style uber material. Base color, metallic and transmission blend between
+// an opaque dielectric (diffuse with sheen under a Schlick specular layer), a metal tinted by
+// the base color and rough glass, all sharing one roughness. An optional clearcoat sits on top.
+// Each scatter stochastically picks one lobe and divides by the probability of picking it.
+pub struct Principled {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    clearcoat: f64,
+    clearcoat_roughness: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    transmission: f64,
+    ir: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Self {
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            transmission: 0.0,
+            ir: 1.5,
+        }
+    }
+
+    pub fn with_metallic(self, metallic: f64) -> Self {
+        Principled { metallic, ..self }
+    }
+
+    pub fn with_roughness(self, roughness: f64) -> Self {
+        Principled { roughness, ..self }
+    }
+
+    // Dielectric reflectance at normal incidence, scaled so the default of 0.5 gives 4%
+    pub fn with_specular(self, specular: f64) -> Self {
+        Principled { specular, ..self }
+    }
+
+    pub fn with_clearcoat(self, clearcoat: f64, clearcoat_roughness: f64) -> Self {
+        Principled { clearcoat, clearcoat_roughness, ..self }
+    }
+
+    // Grazing retro-reflection for cloth, tinted towards the base color by `sheen_tint`
+    pub fn with_sheen(self, sheen: f64, sheen_tint: f64) -> Self {
+        Principled { sheen, sheen_tint, ..self }
+    }
+
+    pub fn with_transmission(self, transmission: f64, index_of_refraction: f64) -> Self {
+        Principled { transmission, ir: index_of_refraction, ..self }
+    }
+
+    fn scatter_clearcoat(&self, wo: Vec3, frame: &Onb, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let (wi, h, masking) = Ggx::new(self.clearcoat_roughness).sample_reflection(wo)?;
+
+        // Picked with probability equal to the coat's Fresnel reflectance at the viewing angle
+        let picked = self.clearcoat * fresnel_schlick(Color::new(0.04, 0.04, 0.04), wo.z()).x();
+        let reflectance = self.clearcoat * fresnel_schlick(Color::new(0.04, 0.04, 0.04), wo.dot(&h)).x();
+        Some((Color::new(1,1,1) * (masking * reflectance / picked), Ray::new(rec.get_p(), frame.to_world(wi))))
+    }
+
+    fn scatter_dielectric(&self, wo: Vec3, frame: &Onb, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let f0 = 0.08 * self.specular * Color::new(1,1,1);
+        let specular_probability = fresnel_schlick(f0, wo.z()).x();
+
+        let mut rng = thread_rng();
+        if rng.gen::<f64>() < specular_probability {
+            let (wi, h, masking) = Ggx::new(self.roughness).sample_reflection(wo)?;
+            let attenuation = fresnel_schlick(f0, wo.dot(&h)) * (masking / specular_probability);
+            return Some((attenuation, Ray::new(rec.get_p(), frame.to_world(wi))))
+        }
+
+        // Cosine weighted diffuse, which cancels the Lambertian 1/pi and cosine
+        let mut wi = Vec3::new(0, 0, 1) + Vec3::random_unit_vector();
+        if wi.is_near_zero() {
+            wi = Vec3::new(0, 0, 1);
+        }
+        let wi = wi.unit_vector();
+
+        let h = (wo + wi).unit_vector();
+        let sheen_color = (1.0 - self.sheen_tint) * Color::new(1,1,1) + self.sheen_tint * self.base_color;
+        // Unlike the diffuse term, Disney's sheen has no 1/pi, so dividing by the pdf leaves a pi
+        let sheen = PI * self.sheen * (1.0 - wi.dot(&h)).clamp(0.0, 1.0).powi(5) * sheen_color;
+
+        // What the specular layer didn't reflect reaches the diffuse base
+        let transmitted = (1.0 - fresnel_schlick(f0, wo.z()).x()) / (1.0 - specular_probability);
+        Some(((self.base_color + sheen) * transmitted, Ray::new(rec.get_p(), frame.to_world(wi))))
+    }
+}
+
+impl Scatter for Principled {
+    fn name(&self) -> &'static str {
+        "principled"
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Onb::new(rec.get_normal());
+        let wo = frame.to_local(-r_in.direction().unit_vector());
+        let mut rng = thread_rng();
+
+        // A ray inside the object only got there through the glass lobe, so it leaves the same
+        // way. The base color already tinted it on the way in.
+        if !rec.front_face {
+            return RoughDielectric::new(self.ir, self.roughness).scatter(r_in, rec)
+        }
+
+        // Clearcoat only applies to the outside of the surface
+        if self.clearcoat > 0.0 {
+            let coat = self.clearcoat * fresnel_schlick(Color::new(0.04, 0.04, 0.04), wo.z()).x();
+            if rng.gen::<f64>() < coat {
+                return self.scatter_clearcoat(wo, &frame, rec)
+            }
+        }
+
+        // The remaining lobes are mixed by their blend weights, which sum to one, so picking
+        // by weight leaves each lobe's own throughput
+        let pick = rng.gen::<f64>();
+        if pick < self.metallic {
+            Conductor::schlick(self.base_color, self.roughness).scatter(r_in, rec)
+        } else if pick < self.metallic + (1.0 - self.metallic) * self.transmission {
+            let (attenuation, scattered) = RoughDielectric::new(self.ir, self.roughness).scatter(r_in, rec)?;
+            // Only light entering the object is tinted, not the reflection off its surface
+            let entering = scattered.direction().dot(&rec.get_geometric_normal()) < 0.0;
+            Some((if entering { attenuation * self.base_color } else { attenuation }, scattered))
+        } else {
+            self.scatter_dielectric(wo, &frame, rec)
+        }
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.base_color
+    }
+}
+
+// Picks one of two materials per scatter. The mask's luminance at the hit is the chance of
+// picking `second`, so a constant 0.3 gives a 70/30 blend and an image mask paints one
+// material over the other.
+pub struct MixMaterial {
+    first: Arc<dyn Scatter>,
+    second: Arc<dyn Scatter>,
+    mask: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(first: Arc<dyn Scatter>, second: Arc<dyn Scatter>, amount: f64) -> Self {
+        MixMaterial::textured(first, second, Arc::new(SolidColor(Color::new(amount, amount, amount))))
+    }
+
+    pub fn textured(first: Arc<dyn Scatter>, second: Arc<dyn Scatter>, mask: Arc<dyn Texture>) -> Self {
+        MixMaterial { first, second, mask }
+    }
+
+    fn amount(&self, rec: &HitRecord) -> f64 {
+        texture_at(self.mask.as_ref(), rec).luminance().clamp(0.0, 1.0)
+    }
+}
+
+impl Scatter for MixMaterial {
+    fn name(&self) -> &'static str {
+        "mix"
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        if thread_rng().gen::<f64>() < self.amount(rec) {
+            self.second.scatter(r_in, rec)
+        } else {
+            self.first.scatter(r_in, rec)
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let amount = self.amount(rec);
+        (1.0 - amount) * self.first.albedo(rec) + amount * self.second.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let amount = self.amount(rec);
+        let blend = (1.0 - amount) * self.first.shading_normal(rec) + amount * self.second.shading_normal(rec);
+        if blend.is_near_zero() { rec.get_normal() } else { blend.unit_vector() }
+    }
+}
+
+// A base material under a thin dielectric coat, like varnish or lacquer. The coat reflects
+// by Fresnel off GGX microfacets; whatever it doesn't reflect scatters from the base as if
+// the coat weren't there. The coat is infinitely thin, so refraction into it is ignored.
+pub struct Coated {
+    base: Arc<dyn Scatter>,
+    ir: f64,
+    distribution: Ggx,
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Scatter>, index_of_refraction: f64, roughness: f64) -> Self {
+        Coated {
+            base,
+            ir: index_of_refraction,
+            distribution: Ggx::new(roughness),
+        }
+    }
+}
+
+impl Scatter for Coated {
+    fn name(&self) -> &'static str {
+        "coated"
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        if !rec.front_face {
+            return self.base.scatter(r_in, rec)
+        }
+
+        let frame = Onb::new(rec.get_normal());
+        let wo = frame.to_local(-r_in.direction().unit_vector());
+
+        let mut rng = thread_rng();
+        let h = self.distribution.sample_visible_normal(wo, rng.gen(), rng.gen());
+        if rng.gen::<f64>() >= fresnel_dielectric(wo.dot(&h), self.ir) {
+            return self.base.scatter(r_in, rec)
+        }
+
+        // Choosing the coat by Fresnel cancels it out of the weight
+        let wi = (-wo).reflect(&h);
+        if wi.z() <= 0.0 {
+            return None
+        }
+        let masking = self.distribution.g(wo, wi) / self.distribution.g1(wo);
+        Some((Color::new(masking, masking, masking), Ray::new(rec.get_p(), frame.to_world(wi))))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.base.shading_normal(rec)
+    }
+}
+
+// Where a NormalMapped material gets its shading normal from
+pub enum NormalSource {
+    // Tangent-space normal map, stored as (x, y, z) * 0.5 + 0.5 in linear RGB with +z out of
+    // the surface, +x along u and +y along v
+    TangentSpace(Arc<dyn Texture>),
+    // Height field whose luminance displaces the surface by up to `scale` world units
+    Height { texture: Arc<dyn Texture>, scale: f64 },
+}
+
+// Wraps a material and perturbs the shading normal before scattering, leaving the geometric
+// normal alone. Surfaces without tangents (such as the poles of a sphere) are left unperturbed.
+pub struct NormalMapped {
+    inner: Arc<dyn Scatter>,
+    source: NormalSource,
+}
+
+impl NormalMapped {
+    pub fn normal_map(inner: Arc<dyn Scatter>, map: Arc<dyn Texture>) -> Self {
+        NormalMapped { inner, source: NormalSource::TangentSpace(map) }
+    }
+
+    pub fn bump_map(inner: Arc<dyn Scatter>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        NormalMapped { inner, source: NormalSource::Height { texture: height, scale } }
+    }
+
+    // The hit with its normal replaced by the map's, if the surface has tangents to map with
+    fn mapped(&self, rec: &HitRecord) -> Option<HitRecord> {
+        self.map_normal(rec).map(|normal| rec.with_shading_normal(normal))
+    }
+
+    fn map_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        let n = rec.get_normal();
+        let (dpdu, dpdv) = (rec.get_tangent(), rec.get_bitangent());
+        if dpdu.is_near_zero() || dpdv.is_near_zero() {
+            return None
+        }
+
+        match &self.source {
+            NormalSource::TangentSpace(map) => {
+                let t = (dpdu - n.dot(&dpdu) * n).unit_vector();
+                let b = n.cross(&t);
+                let b = if b.dot(&dpdv) < 0.0 { -b } else { b };
+                let c = 2.0 * texture_at(map.as_ref(), rec) - Color::new(1, 1, 1);
+                Some(c.x() * t + c.y() * b + c.z() * n)
+            }
+            NormalSource::Height { texture, scale } => {
+                // Forward differences of the height along u and v
+                const DELTA: f64 = 0.0005;
+                let (u, v) = rec.get_uv();
+                let p = rec.get_p();
+                let height = |u: f64, v: f64| scale * texture.value(u, v, &p).luminance();
+                let h = height(u, v);
+                let dhdu = (height(u + DELTA, v) - h) / DELTA;
+                let dhdv = (height(u, v + DELTA) - h) / DELTA;
+                Some((dpdu + dhdu * n).cross(&(dpdv + dhdv * n)))
+            }
+        }
+    }
+}
+
+impl Scatter for NormalMapped {
+    fn name(&self) -> &'static str {
+        "normal mapped"
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        match self.mapped(rec) {
+            Some(mapped) => self.inner.scatter(r_in, &mapped),
+            None => self.inner.scatter(r_in, rec),
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.inner.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        match self.mapped(rec) {
+            Some(mapped) => self.inner.shading_normal(&mapped),
+            None => self.inner.shading_normal(rec),
+        }
+    }
+}
+
+// Subsurface scattering by a random walk through the inside of a closed shape, for wax, skin
+// and marble. Light refracts in through a smooth boundary and scatters isotropically inside a
+// homogeneous medium until it refracts back out. The material has no access to the scene, so
+// the walk relies on every scattered ray hitting the shape's own back faces next: each hit
+// from inside tells it how far the ray travelled, which decides whether the ray scattered on
+// the way. Each internal scattering event uses up a bounce, so a mean free path much smaller
+// than the object needs a higher max depth to avoid darkening.
+pub struct Subsurface {
+    // Single-scattering albedo: the chance a collision scatters rather than absorbs, per channel
+    albedo: Color,
+    // Average distance between collisions, per channel, in world units
+    mean_free_path: Color,
+    ir: f64,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: Color, index_of_refraction: f64) -> Self {
+        Subsurface {
+            albedo,
+            mean_free_path,
+            ir: index_of_refraction,
+        }
+    }
+
+    fn transmittance(&self, distance: f64) -> Color {
+        let m = self.mean_free_path;
+        Color::new((-distance / m.x()).exp(), (-distance / m.y()).exp(), (-distance / m.z()).exp())
+    }
+
+    // Crosses the smooth boundary, or reflects off it with the Fresnel probability
+    fn cross_boundary(&self, r_in: &Ray, rec: &HitRecord) -> Ray {
+        let eta = match rec.front_face {
+            true => self.ir,
+            false => 1.0/self.ir,
+        };
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = (-unit_direction.dot(&rec.get_normal())).min(1.0);
+
+        let direction = if thread_rng().gen::<f64>() < fresnel_dielectric(cos_theta, eta) {
+            unit_direction.reflect(&rec.get_normal())
+        } else {
+            unit_direction.refract(rec.get_normal(), 1.0/eta)
+        };
+        Ray::new(rec.get_p(), direction)
+    }
+}
+
+impl Scatter for Subsurface {
+    fn name(&self) -> &'static str {
+        "subsurface"
+    }
+
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        if rec.front_face {
+            return Some((Color::new(1, 1, 1), self.cross_boundary(r_in, rec)))
+        }
+
+        // Free-flight distances are sampled from one randomly chosen channel's mean free path.
+        // Weights divide by the average over channels of the chance of that outcome, which is
+        // exact for every channel.
+        let segment = rec.get_t() * r_in.direction().length();
+        let mut rng = thread_rng();
+        let channel = rng.gen_range(0..3);
+        let mfp = [self.mean_free_path.x(), self.mean_free_path.y(), self.mean_free_path.z()][channel];
+        let distance = -mfp * (1.0 - rng.gen::<f64>()).ln();
+        let average = |c: Color| (c.x() + c.y() + c.z()) / 3.0;
+
+        if distance < segment {
+            let t = distance / r_in.direction().length();
+            let transmittance = self.transmittance(distance);
+            let m = self.mean_free_path;
+            let density = Color::new(transmittance.x() / m.x(), transmittance.y() / m.y(), transmittance.z() / m.z());
+            let weight = self.albedo * density / average(density);
+            return Some((weight, Ray::new(r_in.at(t), Vec3::random_unit_vector())))
+        }
+
+        let transmittance = self.transmittance(segment);
+        Some((transmittance / average(transmittance), self.cross_boundary(r_in, rec)))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::Hit;
+    use crate::shapes::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn oren_nayar_is_lambertian_without_roughness() {
+        let albedo = Color::new(0.8, 0.5, 0.2);
+        let material = OrenNayar::new(albedo, 0.0);
+        assert_eq!(material.a, 1.0);
+        assert_eq!(material.b, 0.0);
+
+        for _ in 0..1000 {
+            let wo = (Vec3::new(0, 0, 1) + Vec3::random_unit_vector()).unit_vector();
+            let wi = (Vec3::new(0, 0, 1) + Vec3::random_unit_vector()).unit_vector();
+            let weight = material.factor(&wo, &wi) * albedo;
+            assert_eq!(weight, albedo);
+        }
+    }
+
+    // Smooth, so the microfacet sampler never rejects a direction and every loss would be a lobe
+    // picked wrongly
+    #[test]
+    fn principled_always_leaves_transmissive_objects_untinted() {
+        let material = Principled::new(Color::new(0.2, 0.4, 0.8))
+            .with_metallic(0.5)
+            .with_roughness(0.0)
+            .with_transmission(0.5, 1.5);
+        let sphere = Sphere::new(Point3::zero(), 1, Arc::new(material));
+        let r = Ray::new(Point3::zero(), Vec3::new(0.3, 0.2, 1.0));
+        let rec = sphere.hit(&r, 0.0001, f64::INFINITY).unwrap();
+        assert!(!rec.front_face);
+
+        for _ in 0..1000 {
+            let (attenuation, _) = rec.get_mat().scatter(&r, &rec).expect("absorbed inside the object");
+            assert_eq!(attenuation.x(), attenuation.y());
+            assert_eq!(attenuation.y(), attenuation.z());
+        }
+    }
+
+    #[test]
+    fn normal_maps_change_the_reported_shading_normal() {
+        // Tilts the normal 45 degrees towards the tangent
+        let map = Arc::new(SolidColor(Color::new(0.85, 0.5, 0.85)));
+        let material = NormalMapped::normal_map(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))), map);
+        let sphere = Sphere::new(Point3::zero(), 1, Arc::new(material));
+        let r = Ray::new(Point3::new(0, 0, 5), Vec3::new(0, 0, -1));
+        let rec = sphere.hit(&r, 0.0001, f64::INFINITY).unwrap();
+
+        let normal = rec.get_mat().shading_normal(&rec);
+        let expected = Vec3::new(1, 0, 1).unit_vector();
+        assert!((normal - expected).length() < 1e-9, "{} != {}", normal, expected);
+    }
+
+    #[test]
+    fn oren_nayar_roughness_changes_the_weight() {
+        let material = OrenNayar::new(Color::new(1, 1, 1), 0.5);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        // Light returning towards the viewer stays brighter than light scattered away from it,
+        // which loses energy compared to a Lambertian surface
+        let forward = material.factor(&wo, &Vec3::new(-0.6, 0.0, 0.8));
+        assert!(material.factor(&wo, &wo) > forward);
+        assert!(forward < 1.0);
     }
 }
\ No newline at end of file
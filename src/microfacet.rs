@@ -0,0 +1,150 @@
+use std::f64::consts::PI;
+
+use rand::{thread_rng, Rng};
+
+use crate::vec3::{Color, Vec3};
+
+// Shared pieces for microfacet materials. Directions are handled in a local shading frame where
+// the surface normal is +z, and point away from the surface.
+
+// Orthonormal basis around a normal
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new(normal: Vec3) -> Self {
+        let w = normal.unit_vector();
+        let a = if w.x().abs() > 0.9 { Vec3::new(0, 1, 0) } else { Vec3::new(1, 0, 0) };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Onb { u, v, w }
+    }
+
+    pub fn to_local(&self, d: Vec3) -> Vec3 {
+        Vec3::new(d.dot(&self.u), d.dot(&self.v), d.dot(&self.w))
+    }
+
+    pub fn to_world(&self, d: Vec3) -> Vec3 {
+        d.x() * self.u + d.y() * self.v + d.z() * self.w
+    }
+}
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith masking-shadowing
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+
+    // Perceptual roughness in [0, 1], squared to get alpha. Kept slightly above zero, where the
+    // distribution degenerates to a perfect mirror.
+    pub fn new(roughness: f64) -> Self {
+        Ggx {
+            alpha: (roughness * roughness).max(1e-4),
+        }
+    }
+
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0
+        }
+        let a2 = self.alpha * self.alpha;
+        let denom = h.z() * h.z() * (a2 - 1.0) + 1.0;
+        a2 / (PI * denom * denom)
+    }
+
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return f64::INFINITY
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated masking-shadowing for a pair of directions
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Samples a microfacet normal from the distribution of normals visible from `wo`
+    // (Heitz 2018, "Sampling the GGX Distribution of Visible Normals")
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        let vh = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).unit_vector();
+
+        let len2 = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len2 > 0.0 { Vec3::new(-vh.y(), vh.x(), 0) / len2.sqrt() } else { Vec3::new(1, 0, 0) };
+        let t2 = vh.cross(&t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(0.0)).unit_vector()
+    }
+
+    // Reflects `wo` off a sampled visible microfacet. Returns the reflected direction, the
+    // microfacet normal and the G2/G1 weight left over from sampling, or None when the
+    // reflection points into the surface.
+    pub fn sample_reflection(&self, wo: Vec3) -> Option<(Vec3, Vec3, f64)> {
+        let mut rng = thread_rng();
+        let h = self.sample_visible_normal(wo, rng.gen(), rng.gen());
+        let wi = (-wo).reflect(&h);
+        if wi.z() <= 0.0 {
+            return None
+        }
+        Some((wi, h, self.g(wo, wi) / self.g1(wo)))
+    }
+}
+
+pub fn fresnel_schlick(f0: Color, cos_theta: f64) -> Color {
+    f0 + (1.0 - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+// Unpolarised Fresnel reflectance of a conductor with complex index of refraction eta + ik,
+// evaluated per color channel
+pub fn fresnel_conductor(cos_theta: f64, eta: Color, k: Color) -> Color {
+    let f = |eta: f64, k: f64| {
+        let cos2 = cos_theta * cos_theta;
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+        let t1 = a2_plus_b2 + cos2;
+        let t2 = 2.0 * cos_theta * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rp + rs)
+    };
+    Color::new(f(eta.x(), k.x()), f(eta.y(), k.y()), f(eta.z(), k.z()))
+}
+
+// Unpolarised Fresnel reflectance at a dielectric boundary. `eta` is the ratio of the index on
+// the far side to the index on the incident side, and `cos_i` is measured on the incident side.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
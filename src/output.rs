@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use exr::prelude::{Image, SpecificChannels, WritableImage};
+use exr::meta::attribute::Chromaticities;
+use exr::math::Vec2;
+use image::{ImageBuffer, ImageError, ImageFormat, ImageResult, Rgb, RgbaImage};
+use image::codecs::hdr::HdrEncoder;
+use image::error::{EncodingError, ImageFormatHint};
+
+use crate::color::SRGB_PRIMARIES;
+use crate::tonemap::ToneMap;
+use crate::vec3::Color;
+
+// The framebuffer handed to these functions holds linear radiance, one Color per pixel,
+// with rows ordered top to bottom. Tone mapping only applies to 8-bit output; the float
+// formats store the radiance untouched.
+
+pub struct OutputSettings {
+    pub tone_map: ToneMap,
+    // Tag PNG output with an sRGB chunk and EXR output with sRGB/Rec.709 chromaticities.
+    // Radiance HDR and PFM have no place for it and are always written as linear sRGB primaries.
+    pub embed_color_space: bool,
+}
+
+pub fn to_rgba_image(width: u32, height: u32, pixels: &[Color], tone_map: &ToneMap) -> RgbaImage {
+    let raw = pixels.iter()
+                    .flat_map(|c| tone_map.apply(*c).to_rgba(255))
+                    .collect();
+    ImageBuffer::from_vec(width, height, raw).unwrap()
+}
+
+pub fn save(path: &Path, width: u32, height: u32, pixels: &[Color], settings: &OutputSettings) -> ImageResult<()> {
+    let extension = path.extension()
+                        .and_then(|e| e.to_str())
+                        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("exr") => save_exr(path, width, height, pixels, settings.embed_color_space),
+        Some("hdr") => save_hdr(path, width, height, pixels),
+        Some("pfm") => save_pfm(path, width, height, pixels),
+        Some("png") if settings.embed_color_space => {
+            save_srgb_png(path, &to_rgba_image(width, height, pixels, &settings.tone_map))
+        }
+        _ => to_rgba_image(width, height, pixels, &settings.tone_map).save(path),
+    }
+}
+
+// Where an extra pass is written: image.png becomes image.albedo.png
+pub fn aov_path(output: &Path, name: &str) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    match output.extension().and_then(|e| e.to_str()) {
+        Some(ext) => output.with_file_name(format!("{}.{}.{}", stem, name, ext)),
+        None => output.with_file_name(format!("{}.{}", stem, name)),
+    }
+}
+
+// Where one frame of a sequence is written: image.png becomes image_0001.png
+pub fn frame_path(output: &Path, frame: u32) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    match output.extension().and_then(|e| e.to_str()) {
+        Some(ext) => output.with_file_name(format!("{}_{:04}.{}", stem, frame, ext)),
+        None => output.with_file_name(format!("{}_{:04}", stem, frame)),
+    }
+}
+
+fn save_exr(path: &Path, width: u32, height: u32, pixels: &[Color], embed_color_space: bool) -> ImageResult<()> {
+    let w = width as usize;
+    let channels = SpecificChannels::rgb(|pos: Vec2<usize>| {
+        let [r, g, b] = pixels[pos.y() * w + pos.x()].to_rgb_f32();
+        (r, g, b)
+    });
+
+    let mut image = Image::from_channels((w, height as usize), channels);
+    if embed_color_space {
+        let [red, green, blue, white] = SRGB_PRIMARIES.map(|(x, y)| Vec2(x, y));
+        image.attributes.chromaticities = Some(Chromaticities { red, green, blue, white });
+    }
+
+    image.write()
+         .to_file(path)
+         .map_err(|e| encoding_error(ImageFormat::OpenExr, e))
+}
+
+fn save_hdr(path: &Path, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+    let data: Vec<Rgb<f32>> = pixels.iter()
+                                    .map(|c| Rgb(c.to_rgb_f32()))
+                                    .collect();
+    let file = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(file).encode(&data, width as usize, height as usize)
+}
+
+// Portable float map: a tiny text header followed by raw little-endian f32 RGB triples,
+// with scanlines stored bottom to top.
+fn save_pfm(path: &Path, width: u32, height: u32, pixels: &[Color]) -> ImageResult<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    for row in pixels.chunks(width as usize).rev() {
+        for channel in row.iter().flat_map(|c| c.to_rgb_f32()) {
+            file.write_all(&channel.to_le_bytes())?;
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+// The image crate's PNG encoder can't write ancillary chunks, so go through png directly
+fn save_srgb_png(path: &Path, image: &RgbaImage) -> ImageResult<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    let mut writer = encoder.write_header()
+                            .map_err(|e| encoding_error(ImageFormat::Png, e))?;
+    writer.write_image_data(image.as_raw())
+          .map_err(|e| encoding_error(ImageFormat::Png, e))
+}
+
+fn encoding_error<E>(format: ImageFormat, err: E) -> ImageError
+    where E: std::error::Error + Send + Sync + 'static
+{
+    ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(format), err))
+}
@@ -4,12 +4,18 @@ use crate::vec3::{Vec3, Point3};
 
 pub struct Ray {
     orig: Point3,
-    dir: Vec3
+    dir: Vec3,
+    // Wavelength in nanometres carried by rays in spectral mode
+    wavelength: Option<f64>,
 }
 
 impl Ray {
     pub fn new(orig: Point3, dir: Vec3) -> Ray {
-        Ray {orig, dir}
+        Ray {orig, dir, wavelength: None}
+    }
+
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Ray {
+        Ray {wavelength, ..self}
     }
 
     pub fn at(&self, t: f64 ) -> Point3 {
@@ -24,6 +30,10 @@ impl Ray {
         self.orig
     }
 
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
 }
 
 impl Display for Ray {
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use indicatif::ProgressBar;
+use rand::{thread_rng, Rng};
+use rayon::prelude::*;
+
+use crate::camera::{Project, Projection};
+use crate::camera::distortion::LensProfile;
+use crate::camera::stereo::StereoRig;
+use crate::film::{AovSample, Film, Splats};
+use crate::filter::Filter;
+use crate::materials::Scatter;
+use crate::ray::Ray;
+use crate::shapes::{Hit, HittableList};
+use crate::spectrum::{sample_wavelength, wavelength_weight};
+use crate::vec3::{Color, Vec3};
+
+// Everything needed to reproduce a render, and stored alongside it in checkpoints
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u64,
+    pub max_depth: i32,
+    pub seed: u64,
+    pub filter: Filter,
+    // Trace a single wavelength per path, for dispersion
+    pub spectral: bool,
+    pub projection: Projection,
+    pub stereo: Option<StereoRig>,
+    pub crop: Option<Crop>,
+    // Distortion of the perspective camera
+    pub lens: Option<LensProfile>,
+    // Image point the perspective camera focuses through, as fractions from the top left
+    pub autofocus: Option<(f64, f64)>,
+}
+
+// Region of the image to render, in pixels from its top left corner. The film only covers the
+// crop, but rays are traced exactly as for the full image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderSettings {
+    // Size of the film, which is the crop window if there is one
+    pub fn film_size(&self) -> (u32, u32) {
+        self.crop.map_or((self.width, self.height), |crop| (crop.width, crop.height))
+    }
+
+    // Image position of the film's first pixel, with rows counted from the bottom like the film
+    fn film_origin(&self) -> (u32, u32) {
+        self.crop.map_or((0, 0), |crop| (crop.x, self.height - crop.y - crop.height))
+    }
+
+    // Pixels of the image outside the crop window but within `margin` of it, in film coordinates
+    // and limited to the film rows in `rows`
+    fn crop_margin(&self, margin: i64, rows: Range<i64>) -> Vec<(i64, i64)> {
+        let Some(crop) = self.crop else {
+            return Vec::new()
+        };
+        let (x0, y0) = self.film_origin();
+        let (width, height) = (crop.width as i64, crop.height as i64);
+        let columns = (-margin).max(-(x0 as i64))..(width + margin).min((self.width - x0) as i64);
+        let rows = rows.start.max(-margin).max(-(y0 as i64))..rows.end.min(height + margin).min((self.height - y0) as i64);
+
+        rows.flat_map(|j| columns.clone().map(move |i| (i, j)))
+            .filter(|&(i, j)| !(0..width).contains(&i) || !(0..height).contains(&j))
+            .collect()
+    }
+}
+
+// Rows rendered by one task. Each band splats into a buffer that overlaps its neighbours by the
+// filter radius, so bands can be rendered in parallel and merged afterwards.
+const BAND_ROWS: u32 = 8;
+
+pub fn ray_color(r: &Ray, world: &dyn Hit, depth: i32) -> Color {
+
+    if depth <= 0 {
+        return Color::zero()
+    }
+
+    let res = world.hit(r, 0.0001, f64::INFINITY);
+
+    if let Some(shape) = res {
+        let scatter = shape.get_mat().scatter(r, &shape);
+        if let Some((att, scat)) = scatter {
+            return att * ray_color(&scat.with_wavelength(r.wavelength()), world, depth-1)
+        }
+        return Color::new(0,0,0);
+    }
+
+    background(r)
+}
+
+// ray_color, printing every bounce of the path
+fn trace_path(r: &Ray, world: &dyn Hit, depth: i32, bounce: i32) -> Color {
+    println!("  bounce {}: ray {}", bounce, r);
+    if depth <= 0 {
+        println!("    depth limit reached");
+        return Color::zero()
+    }
+
+    match world.hit(r, 0.0001, f64::INFINITY) {
+        Some(rec) => {
+            println!("    hit {}", rec);
+            match rec.get_mat().scatter(r, &rec) {
+                Some((att, scat)) => {
+                    println!("    attenuation {}", att);
+                    att * trace_path(&scat.with_wavelength(r.wavelength()), world, depth-1, bounce+1)
+                }
+                None => {
+                    println!("    absorbed");
+                    Color::zero()
+                }
+            }
+        }
+        None => {
+            let color = background(r);
+            println!("    missed, background {}", color);
+            color
+        }
+    }
+}
+
+fn background(r: &Ray) -> Color {
+    let unit_direction: Vec3 = r.direction().unit_vector();
+    let t = 0.5 * (unit_direction.y() + 1.0);
+    (1.0 - t) * Color::new(1, 1, 1) + t*Color::new(0.5, 0.7, 1.0)
+}
+
+// Numbers the world's materials from 1 in the order objects first use them, so material IDs
+// stay stable between runs of the same scene
+pub struct MaterialIds(HashMap<usize, u32>);
+
+impl MaterialIds {
+    pub fn new(world: &HittableList) -> Self {
+        let mut ids = HashMap::new();
+        for mat in world.iter().filter_map(|object| object.material()) {
+            let next = ids.len() as u32 + 1;
+            ids.entry(MaterialIds::key(&mat)).or_insert(next);
+        }
+        MaterialIds(ids)
+    }
+
+    pub fn get(&self, mat: &Arc<dyn Scatter>) -> u32 {
+        self.0.get(&MaterialIds::key(mat)).copied().unwrap_or(0)
+    }
+
+    fn key(mat: &Arc<dyn Scatter>) -> usize {
+        Arc::as_ptr(mat) as *const () as usize
+    }
+}
+
+fn first_hit_aovs(r: &Ray, world: &dyn Hit, material_ids: &MaterialIds) -> AovSample {
+    match world.hit(r, 0.0001, f64::INFINITY) {
+        Some(rec) => AovSample {
+            samples: 1,
+            albedo: rec.get_mat().albedo(&rec),
+            normal: rec.get_mat().shading_normal(&rec),
+            // Camera rays aren't unit length, so scale t to a distance
+            depth: rec.get_t() * r.direction().length(),
+            position: rec.get_p(),
+            object_id: rec.get_object_id(),
+            material_id: material_ids.get(&rec.get_mat()),
+        },
+        None => AovSample::miss(background(r)),
+    }
+}
+
+// Camera ray through image position (x, y), with rows counted from the bottom, and the weight
+// its radiance carries
+fn camera_ray(cam: &dyn Project, settings: &RenderSettings, x: f64, y: f64, wavelength: Option<f64>) -> (Ray, Color) {
+    let (r, weight) = cam.get_weighted_ray(x / ((settings.width-1) as f64), y / ((settings.height-1) as f64));
+    let weight = match wavelength {
+        Some(wavelength) => weight * wavelength_weight(wavelength),
+        None => weight,
+    };
+    (r.with_wavelength(wavelength), weight)
+}
+
+// Adds `samples` more samples to every pixel of the film, one row per progress tick. AOVs are
+// only traced when `material_ids` is given.
+pub fn render_pass(film: &mut Film, cam: &dyn Project, world: &dyn Hit, settings: &RenderSettings,
+                   material_ids: Option<&MaterialIds>, samples: u64, bar: &ProgressBar) {
+    let width = film.width();
+    let height = film.height();
+    let margin = settings.filter.radius().ceil() as i64;
+    // How many pixels away a sample can still land inside the filter of a pixel
+    let reach = ((settings.filter.radius() + 0.5).ceil() as i64 - 1).max(0);
+    let (x0, y0) = settings.film_origin();
+
+    let splats: Vec<Splats> = film.bands_mut(BAND_ROWS)
+        .enumerate()
+        .map(|(band, (counts, aovs))| {
+            let start_row = band as u32 * BAND_ROWS;
+            let mut splats = Splats::new(width, height, start_row as i64 - margin, BAND_ROWS as i64 + 2 * margin);
+            let mut rng = thread_rng();
+
+            for (offset, (count, aov)) in counts.iter_mut().zip(aovs.iter_mut()).enumerate() {
+                let i = offset as u32 % width;
+                let j = start_row + offset as u32 / width;
+
+                for _ in 0..samples {
+                    let x = i as f64 + rng.gen::<f64>();
+                    let y = j as f64 + rng.gen::<f64>();
+
+                    let wavelength = settings.spectral.then(|| sample_wavelength(rng.gen()));
+                    let (r, weight) = camera_ray(cam, settings, x + x0 as f64, y + y0 as f64, wavelength);
+                    let color = ray_color(&r, world, settings.max_depth) * weight;
+                    splats.add(x, y, color, &settings.filter);
+                    if let Some(ids) = material_ids {
+                        aov.add(&first_hit_aovs(&r, world, ids));
+                    }
+                }
+                *count += samples;
+
+                if i == width - 1 {
+                    bar.inc(1);
+                }
+            }
+
+            // Samples just outside a crop window still reach its edge pixels through the filter,
+            // so they are traced too. The first and last bands take the rows below and above it.
+            let first = if band == 0 { i64::MIN } else { start_row as i64 };
+            let last = if start_row + BAND_ROWS >= height { i64::MAX } else { (start_row + BAND_ROWS) as i64 };
+            for (i, j) in settings.crop_margin(reach, first..last) {
+                for _ in 0..samples {
+                    let x = i as f64 + rng.gen::<f64>();
+                    let y = j as f64 + rng.gen::<f64>();
+
+                    let wavelength = settings.spectral.then(|| sample_wavelength(rng.gen()));
+                    let (r, weight) = camera_ray(cam, settings, x + x0 as f64, y + y0 as f64, wavelength);
+                    splats.add(x, y, ray_color(&r, world, settings.max_depth) * weight, &settings.filter);
+                }
+            }
+            splats
+        })
+        .collect();
+
+    for band in &splats {
+        film.add_splats(band);
+    }
+}
+
+
+// Traces `samples` paths through pixel (i, j) of the full image, counting rows from the top,
+// and prints every ray, hit and attenuation along the way
+pub fn debug_pixel(cam: &dyn Project, world: &dyn Hit, settings: &RenderSettings, i: u32, j: u32, samples: u64) {
+    let mut rng = thread_rng();
+    let mut total = Color::zero();
+
+    for sample in 0..samples {
+        let x = i as f64 + rng.gen::<f64>();
+        let y = (settings.height - 1 - j) as f64 + rng.gen::<f64>();
+        let wavelength = settings.spectral.then(|| sample_wavelength(rng.gen()));
+        let (r, weight) = camera_ray(cam, settings, x, y, wavelength);
+
+        println!("Sample {} at ({:.3}, {:.3}), camera weight {}", sample + 1, x, y, weight);
+        if let Some(wavelength) = wavelength {
+            println!("  wavelength {:.1} nm", wavelength);
+        }
+        let color = trace_path(&r, world, settings.max_depth, 0) * weight;
+        println!("  radiance {}", color);
+        total = total + color;
+    }
+    println!("Pixel ({}, {}) mean over {} samples: {}", i, j, samples, total / samples as f64);
+}
\ No newline at end of file
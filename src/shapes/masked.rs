@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use crate::materials::Scatter;
+use crate::ray::Ray;
+use crate::shapes::{Hit, HitRecord};
+use crate::textures::Texture;
+
+// Cutout transparency for any shape. Hits where the mask's luminance at (u, v) falls below
+// `threshold` are skipped and the ray carries on to the shape's next intersection, so leaves,
+// fences and decals can be cut from simple geometry.
+pub struct Masked {
+    shape: Box<dyn Hit>,
+    mask: Arc<dyn Texture>,
+    threshold: f64,
+}
+
+impl Masked {
+    pub fn new(shape: Box<dyn Hit>, mask: Arc<dyn Texture>, threshold: f64) -> Self {
+        Masked { shape, mask, threshold }
+    }
+
+    fn is_opaque(&self, rec: &HitRecord) -> bool {
+        let (u, v) = rec.get_uv();
+        self.mask.value(u, v, &rec.get_p()).luminance() >= self.threshold
+    }
+}
+
+impl Hit for Masked {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut t_min = t_min;
+        loop {
+            let rec = self.shape.hit(r, t_min, t_max)?;
+            if self.is_opaque(&rec) {
+                return Some(rec)
+            }
+            // Shapes accept hits at exactly t_min, so step past this one
+            t_min = rec.get_t() + 1e-9 * rec.get_t().max(1.0);
+        }
+    }
+
+    fn material(&self) -> Option<Arc<dyn Scatter>> {
+        self.shape.material()
+    }
+}
\ No newline at end of file
@@ -1,38 +1,83 @@
 pub mod sphere;
+pub mod masked;
+pub mod packet;
 
 
+use std::fmt::Display;
 use std::sync::Arc;
 
 use crate::materials::Scatter;
+use crate::shapes::sphere::Sphere;
 use crate::vec3::{Point3, Vec3}; 
 use crate::ray::Ray;
 
 pub struct HitRecord {
     p: Point3,
+    // Both normals face against the incoming ray. The shading normal starts out equal to the
+    // geometric one and is what materials scatter around; normal and bump maps perturb it.
     normal: Vec3,
+    geometric_normal: Vec3,
     mat: Arc<dyn Scatter>,
     t: f64,
+    // Surface parameterisation at the hit, for texture lookups
+    uv: (f64, f64),
+    // Partial derivatives of the surface point with respect to u and v, unnormalised
+    tangent: Vec3,
+    bitangent: Vec3,
+    object_id: u32,
     pub front_face: bool
 }
 
 impl HitRecord {
 
-    fn new(p: Point3, t: f64, r: &Ray, outward_normal: &Vec3, mat: Arc<dyn Scatter>) -> Self {
+    fn new(p: Point3, t: f64, uv: (f64, f64), r: &Ray, outward_normal: &Vec3, mat: Arc<dyn Scatter>) -> Self {
         let front_face = r.direction().dot(outward_normal) < 0.0;
         let normal = if front_face {*outward_normal}else{Vec3::zero()-*outward_normal};
         HitRecord {
             p,
             normal,
+            geometric_normal: normal,
             mat,
             t,
+            uv,
+            tangent: Vec3::zero(),
+            bitangent: Vec3::zero(),
+            object_id: 0,
             front_face,
         }
     }
 
+    fn with_tangents(self, tangent: Vec3, bitangent: Vec3) -> Self {
+        HitRecord { tangent, bitangent, ..self }
+    }
+
+    // A copy of this hit with a different shading normal, flipped if needed to stay on the
+    // same side as the geometric normal
+    pub fn with_shading_normal(&self, normal: Vec3) -> Self {
+        let normal = normal.unit_vector();
+        HitRecord {
+            normal: if normal.dot(&self.geometric_normal) < 0.0 { -normal } else { normal },
+            mat: self.mat.clone(),
+            ..*self
+        }
+    }
+
     pub fn get_normal(&self) -> Vec3 {
         self.normal
     }
 
+    pub fn get_geometric_normal(&self) -> Vec3 {
+        self.geometric_normal
+    }
+
+    pub fn get_tangent(&self) -> Vec3 {
+        self.tangent
+    }
+
+    pub fn get_bitangent(&self) -> Vec3 {
+        self.bitangent
+    }
+
     pub fn get_p(&self) -> Point3 {
         self.p
     }
@@ -41,12 +86,42 @@ impl HitRecord {
         self.mat.clone()
     }
 
+    pub fn get_t(&self) -> f64 {
+        self.t
+    }
 
+    pub fn get_uv(&self) -> (f64, f64) {
+        self.uv
+    }
+
+    // 1-based index of the object in the world that was hit
+    pub fn get_object_id(&self) -> u32 {
+        self.object_id
+    }
+
+
+}
+
+impl Display for HitRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "p: {}, t: {}, normal: {}, front face: {}, uv: ({}, {}), object: {}, material: {}",
+               self.p, self.t, self.normal, self.front_face, self.uv.0, self.uv.1, self.object_id, self.mat.name())
+    }
 }
 
 
 pub trait Hit: Send + Sync {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    // The material of a single-material shape, used to number materials for ID passes
+    fn material(&self) -> Option<Arc<dyn Scatter>> {
+        None
+    }
+
+    // Plain spheres identify themselves so PacketList can intersect several at once
+    fn as_sphere(&self) -> Option<&Sphere> {
+        None
+    }
 }
 
 pub type HittableList = Vec<Box<dyn Hit>>;
@@ -58,9 +133,10 @@ impl Hit for HittableList {
         let mut closest_res = None;
         let mut closest_so_far = t_max;
 
-        for object in self {
-            if let Some(rec) = object.hit(r, t_min, closest_so_far){
+        for (index, object) in self.iter().enumerate() {
+            if let Some(mut rec) = object.hit(r, t_min, closest_so_far){
                 closest_so_far = rec.t;
+                rec.object_id = index as u32 + 1;
                 closest_res = Some(rec);
             }
         }
@@ -0,0 +1,189 @@
+use crate::ray::Ray;
+use crate::shapes::{Hit, HitRecord, HittableList};
+use crate::shapes::sphere::Sphere;
+use crate::simd::{F64x4, Vec3x4, LANES};
+use crate::vec3::Point3;
+
+// A ray copied into every lane, built once and tested against each packet
+struct RayLanes {
+    origin: Vec3x4,
+    direction: Vec3x4,
+    length_squared: F64x4,
+}
+
+impl RayLanes {
+    fn new(r: &Ray) -> RayLanes {
+        RayLanes {
+            origin: Vec3x4::splat(r.origin()),
+            direction: Vec3x4::splat(r.direction()),
+            length_squared: F64x4::splat(r.direction().length_squared()),
+        }
+    }
+}
+
+// Up to LANES spheres, intersected with a ray all at once
+struct SpherePacket {
+    center: Vec3x4,
+    radius_squared: F64x4,
+    // Position of each lane's sphere in the object list
+    objects: [usize; LANES],
+}
+
+impl SpherePacket {
+    // Unused lanes get a negative squared radius, which leaves no ray a real root
+    fn new(spheres: &[(usize, &Sphere)]) -> SpherePacket {
+        let mut centers = [Point3::zero(); LANES];
+        let mut radii_squared = [-1.0; LANES];
+        let mut objects = [spheres[0].0; LANES];
+        for (lane, (index, sphere)) in spheres.iter().enumerate() {
+            centers[lane] = sphere.center();
+            radii_squared[lane] = sphere.radius() * sphere.radius();
+            objects[lane] = *index;
+        }
+        SpherePacket {
+            center: Vec3x4::new(centers),
+            radius_squared: F64x4::from_array(radii_squared),
+            objects,
+        }
+    }
+
+    // Closest root in [t_min, t_max] over all lanes, with the object it belongs to. Each lane
+    // does the same arithmetic as Sphere::hit, so the roots agree exactly, and equal roots go to
+    // the later lane, like the later object wins a tie in HittableList::hit.
+    fn nearest(&self, r: &RayLanes, t_min: f64, t_max: f64) -> Option<(f64, usize)> {
+        let oc = r.origin - self.center;
+
+        let a = r.length_squared;
+        let half_b = oc.dot(&r.direction);
+        let c = oc.length_squared() - self.radius_squared;
+
+        // Most rays miss every sphere of a packet, so skip the roots then
+        let discriminant = half_b * half_b - a * c;
+        if !F64x4::splat(0.0).le(discriminant).any() {
+            return None
+        }
+
+        // Lanes with a negative discriminant get NaN roots, which fail every comparison
+        let sqrtd = discriminant.sqrt();
+        let near = (-half_b - sqrtd) / a;
+        let far = (-half_b + sqrtd) / a;
+
+        let (t_min, t_max) = (F64x4::splat(t_min), F64x4::splat(t_max));
+        let near_hit = t_min.le(near).and(near.le(t_max));
+        let far_hit = t_min.le(far).and(far.le(t_max));
+        if !near_hit.or(far_hit).any() {
+            return None
+        }
+
+        let t = F64x4::select(near_hit, near, F64x4::select(far_hit, far, F64x4::splat(f64::INFINITY)));
+        t.to_array()
+         .into_iter()
+         .zip(self.objects)
+         .filter(|(t, _)| t.is_finite())
+         .fold(None, |nearest, (t, index)| match nearest {
+             Some((nearest_t, _)) if nearest_t < t => nearest,
+             _ => Some((t, index)),
+         })
+    }
+}
+
+// The objects of a HittableList with its spheres grouped into packets, so a ray tests LANES of
+// them at a time. Hits, object IDs included, are the same as the list's, even when two objects
+// are hit at exactly the same distance.
+pub struct PacketList {
+    objects: HittableList,
+    packets: Vec<SpherePacket>,
+    // Everything that isn't a plain sphere, tested one at a time
+    others: Vec<usize>,
+}
+
+impl PacketList {
+    pub fn new(objects: HittableList) -> PacketList {
+        let spheres: Vec<(usize, &Sphere)> = objects.iter()
+                                                    .enumerate()
+                                                    .filter_map(|(index, object)| object.as_sphere().map(|s| (index, s)))
+                                                    .collect();
+        let packets = spheres.chunks(LANES).map(SpherePacket::new).collect();
+        let others = (0..objects.len()).filter(|&index| objects[index].as_sphere().is_none()).collect();
+
+        PacketList { objects, packets, others }
+    }
+
+    pub fn objects(&self) -> &HittableList {
+        &self.objects
+    }
+}
+
+impl Hit for PacketList {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut nearest_sphere = None;
+        let mut closest_so_far = t_max;
+
+        let lanes = RayLanes::new(r);
+        for packet in &self.packets {
+            if let Some((t, index)) = packet.nearest(&lanes, t_min, closest_so_far) {
+                closest_so_far = t;
+                nearest_sphere = Some(index);
+            }
+        }
+
+        // Only the winning sphere fills in a full hit record
+        let mut closest_res = nearest_sphere.and_then(|index| {
+            let mut rec = self.objects[index].hit(r, t_min, closest_so_far)?;
+            rec.object_id = index as u32 + 1;
+            Some(rec)
+        });
+
+        let mut nearest = nearest_sphere;
+        for &index in &self.others {
+            if let Some(mut rec) = self.objects[index].hit(r, t_min, closest_so_far) {
+                // The list would have tested a later sphere after this object, and that sphere
+                // would win the tie
+                if rec.t == closest_so_far && nearest.is_some_and(|nearest| nearest > index) {
+                    continue
+                }
+                nearest = Some(index);
+                closest_so_far = rec.t;
+                rec.object_id = index as u32 + 1;
+                closest_res = Some(rec);
+            }
+        }
+
+        closest_res
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::materials::Lambertian;
+    use crate::shapes::masked::Masked;
+    use crate::textures::SolidColor;
+    use crate::vec3::{Color, Vec3};
+
+    fn sphere() -> Box<Sphere> {
+        Box::new(Sphere::new(Point3::new(0, 0, -2), 0.5, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))))
+    }
+
+    fn masked() -> Box<dyn Hit> {
+        Box::new(Masked::new(sphere(), Arc::new(SolidColor(Color::new(1, 1, 1))), 0.5))
+    }
+
+    #[test]
+    fn ties_go_to_the_same_object_as_in_the_list() {
+        let r = Ray::new(Point3::zero(), Vec3::new(0, 0, -1));
+        let layouts: [fn() -> HittableList; 3] = [
+            || vec![sphere(), sphere(), sphere()],
+            || vec![sphere(), masked(), sphere()],
+            || vec![masked(), sphere(), masked(), sphere(), sphere(), sphere(), sphere(), masked()],
+        ];
+        for layout in layouts {
+            let expected = layout().hit(&r, 0.001, f64::INFINITY).unwrap().get_object_id();
+            let packets = PacketList::new(layout());
+            assert_eq!(packets.hit(&r, 0.001, f64::INFINITY).unwrap().get_object_id(), expected);
+        }
+    }
+}
\ No newline at end of file
@@ -1,3 +1,4 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 use crate::materials::Scatter;
@@ -36,9 +37,19 @@ impl Hit for Sphere {
         let p = r.at(root);
         let outward_normal: Vec3 = (p - self.center) / self.radius;
 
-        let rec = HitRecord::new(p, root, r, &outward_normal, self.mat.clone());
+        let (tangent, bitangent) = self.tangents(&outward_normal);
+        let rec = HitRecord::new(p, root, Sphere::uv(&outward_normal), r, &outward_normal, self.mat.clone())
+            .with_tangents(tangent, bitangent);
         Some(rec)
     }
+
+    fn material(&self) -> Option<Arc<dyn Scatter>> {
+        Some(self.mat.clone())
+    }
+
+    fn as_sphere(&self) -> Option<&Sphere> {
+        Some(self)
+    }
 }
 
 impl Sphere {
@@ -49,4 +60,32 @@ impl Sphere {
             mat,
         }
     }
+
+    pub fn center(&self) -> Point3 {
+        self.center
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    // Longitude and latitude of a point on the unit sphere, both in [0, 1]. u runs around the
+    // y axis starting from -x, v from the bottom pole to the top.
+    fn uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // dp/du and dp/dv for the parameterisation in `uv`, given the unit outward normal. dp/dv
+    // degenerates at the poles, where it is left at zero.
+    fn tangents(&self, n: &Vec3) -> (Vec3, Vec3) {
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(n.z(), 0, -n.x());
+        let sin_theta = (1.0 - n.y() * n.y()).max(0.0).sqrt();
+        if sin_theta < 1e-8 {
+            return (dpdu, Vec3::zero())
+        }
+        let dpdv = PI * self.radius * Vec3::new(-n.y() * n.x() / sin_theta, sin_theta, -n.y() * n.z() / sin_theta);
+        (dpdu, dpdv)
+    }
 }
\ No newline at end of file
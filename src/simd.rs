@@ -0,0 +1,443 @@
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Div, Neg};
+
+use crate::vec3::Vec3;
+
+// Four f64 lanes, for working on four rays or shapes at once in structure-of-arrays form. With
+// the `simd` feature, x86_64 builds use SSE2, or AVX when the target has it (for example with
+// RUSTFLAGS="-C target-cpu=native"). Everywhere else the lanes are plain arrays.
+pub const LANES: usize = 4;
+
+// Instruction set the lanes are built on
+pub const BACKEND: &str = backend::NAME;
+
+#[derive(Copy, Clone)]
+pub struct F64x4(backend::Lanes);
+
+// Per-lane true or false, as produced by comparing F64x4s
+#[derive(Copy, Clone)]
+pub struct Mask4(backend::Lanes);
+
+impl F64x4 {
+    pub fn splat(v: f64) -> F64x4 {
+        F64x4(backend::splat(v))
+    }
+
+    pub fn from_array(a: [f64; LANES]) -> F64x4 {
+        F64x4(backend::from_array(a))
+    }
+
+    pub fn to_array(self) -> [f64; LANES] {
+        backend::to_array(self.0)
+    }
+
+    pub fn sqrt(self) -> F64x4 {
+        F64x4(backend::sqrt(self.0))
+    }
+
+    pub fn lt(self, rhs: F64x4) -> Mask4 {
+        Mask4(backend::lt(self.0, rhs.0))
+    }
+
+    pub fn le(self, rhs: F64x4) -> Mask4 {
+        Mask4(backend::le(self.0, rhs.0))
+    }
+
+    // `a` in the lanes where `mask` is set, `b` in the rest
+    pub fn select(mask: Mask4, a: F64x4, b: F64x4) -> F64x4 {
+        F64x4(backend::select(mask.0, a.0, b.0))
+    }
+}
+
+impl Mask4 {
+    pub fn and(self, rhs: Mask4) -> Mask4 {
+        Mask4(backend::and(self.0, rhs.0))
+    }
+
+    pub fn or(self, rhs: Mask4) -> Mask4 {
+        Mask4(backend::or(self.0, rhs.0))
+    }
+
+    pub fn any(self) -> bool {
+        backend::any(self.0)
+    }
+}
+
+impl fmt::Debug for F64x4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "F64x4 {:?}", self.to_array())
+    }
+}
+
+impl Add for F64x4 {
+    type Output = F64x4;
+
+    fn add(self, rhs: F64x4) -> F64x4 {
+        F64x4(backend::add(self.0, rhs.0))
+    }
+}
+
+impl Sub for F64x4 {
+    type Output = F64x4;
+
+    fn sub(self, rhs: F64x4) -> F64x4 {
+        F64x4(backend::sub(self.0, rhs.0))
+    }
+}
+
+impl Mul for F64x4 {
+    type Output = F64x4;
+
+    fn mul(self, rhs: F64x4) -> F64x4 {
+        F64x4(backend::mul(self.0, rhs.0))
+    }
+}
+
+impl Div for F64x4 {
+    type Output = F64x4;
+
+    fn div(self, rhs: F64x4) -> F64x4 {
+        F64x4(backend::div(self.0, rhs.0))
+    }
+}
+
+impl Neg for F64x4 {
+    type Output = F64x4;
+
+    // -0 - x flips the sign of every x, zeros included, exactly like scalar negation
+    fn neg(self) -> F64x4 {
+        F64x4::splat(-0.0) - self
+    }
+}
+
+// Four Vec3s, one per lane
+#[derive(Debug, Copy, Clone)]
+pub struct Vec3x4 {
+    pub x: F64x4,
+    pub y: F64x4,
+    pub z: F64x4,
+}
+
+impl Vec3x4 {
+    pub fn new(v: [Vec3; LANES]) -> Vec3x4 {
+        Vec3x4 {
+            x: F64x4::from_array(v.map(|v| v.x())),
+            y: F64x4::from_array(v.map(|v| v.y())),
+            z: F64x4::from_array(v.map(|v| v.z())),
+        }
+    }
+
+    pub fn splat(v: Vec3) -> Vec3x4 {
+        Vec3x4 { x: F64x4::splat(v.x()), y: F64x4::splat(v.y()), z: F64x4::splat(v.z()) }
+    }
+
+    // Same order of operations as Vec3::dot, so each lane matches it bit for bit
+    pub fn dot(&self, rhs: &Vec3x4) -> F64x4 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn length_squared(&self) -> F64x4 {
+        self.dot(self)
+    }
+}
+
+impl Add for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn add(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4 { x: self.x + rhs.x, y: self.y + rhs.y, z: self.z + rhs.z }
+    }
+}
+
+impl Sub for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn sub(self, rhs: Vec3x4) -> Vec3x4 {
+        Vec3x4 { x: self.x - rhs.x, y: self.y - rhs.y, z: self.z - rhs.z }
+    }
+}
+
+impl Mul<F64x4> for Vec3x4 {
+    type Output = Vec3x4;
+
+    fn mul(self, rhs: F64x4) -> Vec3x4 {
+        Vec3x4 { x: self.x * rhs, y: self.y * rhs, z: self.z * rhs }
+    }
+}
+
+// Masks hold all bits set for true and all clear for false in every backend
+
+#[cfg(all(feature = "simd", target_arch = "x86_64", target_feature = "avx"))]
+mod backend {
+    // The intrinsics are only unsafe because they need the CPU to support them, which the
+    // cfg above guarantees
+    use std::arch::x86_64::*;
+
+    pub const NAME: &str = "AVX";
+
+    pub type Lanes = __m256d;
+
+    #[inline(always)]
+    pub fn splat(v: f64) -> Lanes {
+        unsafe { _mm256_set1_pd(v) }
+    }
+
+    #[inline(always)]
+    pub fn from_array(a: [f64; 4]) -> Lanes {
+        unsafe { _mm256_setr_pd(a[0], a[1], a[2], a[3]) }
+    }
+
+    #[inline(always)]
+    pub fn to_array(v: Lanes) -> [f64; 4] {
+        let mut a = [0.0; 4];
+        // storeu has no alignment requirement and `a` holds exactly four lanes
+        unsafe { _mm256_storeu_pd(a.as_mut_ptr(), v) };
+        a
+    }
+
+    #[inline(always)]
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_add_pd(a, b) }
+    }
+
+    #[inline(always)]
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_sub_pd(a, b) }
+    }
+
+    #[inline(always)]
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_mul_pd(a, b) }
+    }
+
+    #[inline(always)]
+    pub fn div(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_div_pd(a, b) }
+    }
+
+    #[inline(always)]
+    pub fn sqrt(a: Lanes) -> Lanes {
+        unsafe { _mm256_sqrt_pd(a) }
+    }
+
+    #[inline(always)]
+    pub fn lt(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_cmp_pd::<_CMP_LT_OQ>(a, b) }
+    }
+
+    #[inline(always)]
+    pub fn le(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_cmp_pd::<_CMP_LE_OQ>(a, b) }
+    }
+
+    #[inline(always)]
+    pub fn and(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_and_pd(a, b) }
+    }
+
+    #[inline(always)]
+    pub fn or(a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_or_pd(a, b) }
+    }
+
+    #[inline(always)]
+    pub fn select(mask: Lanes, a: Lanes, b: Lanes) -> Lanes {
+        unsafe { _mm256_blendv_pd(b, a, mask) }
+    }
+
+    #[inline(always)]
+    pub fn any(mask: Lanes) -> bool {
+        unsafe { _mm256_movemask_pd(mask) != 0 }
+    }
+}
+
+// SSE2 is part of x86_64 itself, so this needs no target flags. Two registers hold two lanes each.
+#[cfg(all(feature = "simd", target_arch = "x86_64", not(target_feature = "avx")))]
+mod backend {
+    // As above, the intrinsics are safe on any x86_64 CPU
+    use std::arch::x86_64::*;
+
+    pub const NAME: &str = "SSE2";
+
+    pub type Lanes = [__m128d; 2];
+
+    #[inline(always)]
+    fn zip(a: Lanes, b: Lanes, f: impl Fn(__m128d, __m128d) -> __m128d) -> Lanes {
+        [f(a[0], b[0]), f(a[1], b[1])]
+    }
+
+    #[inline(always)]
+    pub fn splat(v: f64) -> Lanes {
+        unsafe { [_mm_set1_pd(v); 2] }
+    }
+
+    #[inline(always)]
+    pub fn from_array(a: [f64; 4]) -> Lanes {
+        unsafe { [_mm_setr_pd(a[0], a[1]), _mm_setr_pd(a[2], a[3])] }
+    }
+
+    #[inline(always)]
+    pub fn to_array(v: Lanes) -> [f64; 4] {
+        let mut a = [0.0; 4];
+        // storeu has no alignment requirement and each half of `a` holds two lanes
+        unsafe {
+            _mm_storeu_pd(a.as_mut_ptr(), v[0]);
+            _mm_storeu_pd(a.as_mut_ptr().add(2), v[1]);
+        }
+        a
+    }
+
+    #[inline(always)]
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| unsafe { _mm_add_pd(a, b) })
+    }
+
+    #[inline(always)]
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| unsafe { _mm_sub_pd(a, b) })
+    }
+
+    #[inline(always)]
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| unsafe { _mm_mul_pd(a, b) })
+    }
+
+    #[inline(always)]
+    pub fn div(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| unsafe { _mm_div_pd(a, b) })
+    }
+
+    #[inline(always)]
+    pub fn sqrt(a: Lanes) -> Lanes {
+        unsafe { [_mm_sqrt_pd(a[0]), _mm_sqrt_pd(a[1])] }
+    }
+
+    #[inline(always)]
+    pub fn lt(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| unsafe { _mm_cmplt_pd(a, b) })
+    }
+
+    #[inline(always)]
+    pub fn le(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| unsafe { _mm_cmple_pd(a, b) })
+    }
+
+    #[inline(always)]
+    pub fn and(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| unsafe { _mm_and_pd(a, b) })
+    }
+
+    #[inline(always)]
+    pub fn or(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| unsafe { _mm_or_pd(a, b) })
+    }
+
+    // SSE2 has no blend, so mask the two sides and combine them
+    #[inline(always)]
+    pub fn select(mask: Lanes, a: Lanes, b: Lanes) -> Lanes {
+        let a = and(mask, a);
+        let b = zip(mask, b, |m, b| unsafe { _mm_andnot_pd(m, b) });
+        or(a, b)
+    }
+
+    #[inline(always)]
+    pub fn any(mask: Lanes) -> bool {
+        unsafe { _mm_movemask_pd(mask[0]) | _mm_movemask_pd(mask[1]) != 0 }
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+mod backend {
+    use std::array;
+
+    pub const NAME: &str = "scalar";
+
+    pub type Lanes = [f64; 4];
+
+    const TRUE: f64 = f64::from_bits(u64::MAX);
+
+    #[inline(always)]
+    fn zip(a: Lanes, b: Lanes, f: impl Fn(f64, f64) -> f64) -> Lanes {
+        array::from_fn(|i| f(a[i], b[i]))
+    }
+
+    #[inline(always)]
+    fn mask(a: Lanes, b: Lanes, f: impl Fn(f64, f64) -> bool) -> Lanes {
+        zip(a, b, |a, b| if f(a, b) { TRUE } else { 0.0 })
+    }
+
+    #[inline(always)]
+    fn bits(a: Lanes, b: Lanes, f: impl Fn(u64, u64) -> u64) -> Lanes {
+        zip(a, b, |a, b| f64::from_bits(f(a.to_bits(), b.to_bits())))
+    }
+
+    #[inline(always)]
+    pub fn splat(v: f64) -> Lanes {
+        [v; 4]
+    }
+
+    #[inline(always)]
+    pub fn from_array(a: [f64; 4]) -> Lanes {
+        a
+    }
+
+    #[inline(always)]
+    pub fn to_array(v: Lanes) -> [f64; 4] {
+        v
+    }
+
+    #[inline(always)]
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| a + b)
+    }
+
+    #[inline(always)]
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| a - b)
+    }
+
+    #[inline(always)]
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| a * b)
+    }
+
+    #[inline(always)]
+    pub fn div(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |a, b| a / b)
+    }
+
+    #[inline(always)]
+    pub fn sqrt(a: Lanes) -> Lanes {
+        a.map(f64::sqrt)
+    }
+
+    #[inline(always)]
+    pub fn lt(a: Lanes, b: Lanes) -> Lanes {
+        mask(a, b, |a, b| a < b)
+    }
+
+    #[inline(always)]
+    pub fn le(a: Lanes, b: Lanes) -> Lanes {
+        mask(a, b, |a, b| a <= b)
+    }
+
+    #[inline(always)]
+    pub fn and(a: Lanes, b: Lanes) -> Lanes {
+        bits(a, b, |a, b| a & b)
+    }
+
+    #[inline(always)]
+    pub fn or(a: Lanes, b: Lanes) -> Lanes {
+        bits(a, b, |a, b| a | b)
+    }
+
+    #[inline(always)]
+    pub fn select(mask: Lanes, a: Lanes, b: Lanes) -> Lanes {
+        array::from_fn(|i| if mask[i].to_bits() != 0 { a[i] } else { b[i] })
+    }
+
+    #[inline(always)]
+    pub fn any(mask: Lanes) -> bool {
+        mask.iter().any(|m| m.to_bits() != 0)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,122 @@
+use std::sync::OnceLock;
+
+use crate::vec3::Color;
+
+// Spectral mode gives every camera ray a single wavelength, drawn uniformly over the visible
+// range. Materials keep working on RGB colors; only indices of refraction depend on the
+// wavelength. At the pixel the path's color is weighted by the sRGB response to that
+// wavelength, normalised so a path that ignores the wavelength averages back to its RGB color.
+
+pub const MIN_WAVELENGTH: f64 = 380.0;
+pub const MAX_WAVELENGTH: f64 = 780.0;
+
+// Wavelength in nanometres for a uniform random number in [0, 1)
+pub fn sample_wavelength(u: f64) -> f64 {
+    MIN_WAVELENGTH + u * (MAX_WAVELENGTH - MIN_WAVELENGTH)
+}
+
+// Weight applied to the RGB color of a path carrying `wavelength`. Averages to (1, 1, 1) over
+// the visible range. Deep blues and reds fall outside sRGB and give slightly negative channels.
+pub fn wavelength_weight(wavelength: f64) -> Color {
+    static NORMALISATION: OnceLock<Color> = OnceLock::new();
+    let n = NORMALISATION.get_or_init(|| {
+        let steps = 1000;
+        let sum = (0..steps).map(|i| wavelength_to_rgb(sample_wavelength((i as f64 + 0.5) / steps as f64)))
+                            .fold(Color::zero(), |a, b| a + b);
+        sum / steps as f64
+    });
+    let rgb = wavelength_to_rgb(wavelength);
+    Color::new(rgb.x() / n.x(), rgb.y() / n.y(), rgb.z() / n.z())
+}
+
+// Linear sRGB of a monochromatic stimulus, through the CIE 1931 matching functions
+fn wavelength_to_rgb(wavelength: f64) -> Color {
+    let (x, y, z) = cie_xyz(wavelength);
+    Color::new(3.2406 * x - 1.5372 * y - 0.4986 * z,
+               -0.9689 * x + 1.8758 * y + 0.0415 * z,
+               0.0557 * x - 0.2040 * y + 1.0570 * z)
+}
+
+// Multi-lobe Gaussian fit of the CIE 1931 2° observer (Wyman, Sloan & Shirley 2013)
+fn cie_xyz(wavelength: f64) -> (f64, f64, f64) {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if wavelength < mu { sigma_below } else { sigma_above };
+        let t = (wavelength - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    let x = 1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+// Index of refraction, optionally varying with wavelength. Rays without a wavelength (RGB mode)
+// see the index at the sodium d-line, the value glass catalogues quote.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / λ², with λ in micrometres
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ bᵢλ² / (λ² - cᵢ), with λ in micrometres
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+const D_LINE: f64 = 587.6;
+
+impl Ior {
+    // Schott N-BK7 crown glass
+    pub fn bk7() -> Self {
+        Ior::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    // Dense flint glass, roughly four times as dispersive as BK7
+    pub fn flint() -> Self {
+        Ior::Cauchy { a: 1.7, b: 0.0136 }
+    }
+
+    pub fn diamond() -> Self {
+        Ior::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0],
+        }
+    }
+
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let um = wavelength.unwrap_or(D_LINE) / 1000.0;
+        let um2 = um * um;
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy { a, b } => a + b / um2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * um2 / (um2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Catalogue indices at the Fraunhofer F, d and C lines
+    #[test]
+    fn bk7_matches_the_schott_catalogue() {
+        let bk7 = Ior::bk7();
+        assert!((bk7.at(None) - 1.5168).abs() < 1e-4);
+        assert!((bk7.at(Some(486.13)) - 1.5224).abs() < 1e-4);
+        assert!((bk7.at(Some(656.27)) - 1.5143).abs() < 1e-4);
+    }
+
+    #[test]
+    fn diamond_and_flint_disperse_more_than_bk7() {
+        let spread = |ior: Ior| ior.at(Some(486.13)) - ior.at(Some(656.27));
+        assert!((Ior::diamond().at(None) - 2.417).abs() < 1e-3);
+        assert!(spread(Ior::diamond()) > spread(Ior::bk7()));
+        assert!(spread(Ior::flint()) > spread(Ior::bk7()));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use image::{ImageResult, RgbImage};
+
+use crate::color::ColorSpace;
+use crate::vec3::{Color, Point3};
+
+// Spatially varying colors, looked up by the surface (u, v) coordinates or the hit point
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor(pub Color);
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.0
+    }
+}
+
+// 3D checkerboard of cubes `size` units across, so it wraps any shape without seams
+pub struct Checker {
+    even: Color,
+    odd: Color,
+    size: f64,
+}
+
+impl Checker {
+    pub fn new(even: Color, odd: Color, size: f64) -> Self {
+        Checker { even, odd, size }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let cell = |x: f64| (x / self.size).floor() as i64;
+        match (cell(p.x()) + cell(p.y()) + cell(p.z())).rem_euclid(2) {
+            0 => self.even,
+            _ => self.odd,
+        }
+    }
+}
+
+// Image mapped over (u, v) with bilinear filtering, repeating along u. `v` = 0 is the bottom
+// row. Colors are decoded from `space`, so pass ColorSpace::Linear for data such as masks and
+// normal maps.
+pub struct ImageTexture {
+    image: RgbImage,
+    space: ColorSpace,
+}
+
+impl ImageTexture {
+    pub fn open<P: AsRef<Path>>(path: P, space: ColorSpace) -> ImageResult<Self> {
+        Ok(ImageTexture {
+            image: image::open(path)?.to_rgb8(),
+            space,
+        })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let (width, height) = self.image.dimensions();
+        let x = x.rem_euclid(width as i64) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        Color::from_rgb8(self.image.get_pixel(x, y).0, self.space)
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let (width, height) = self.image.dimensions();
+        // Texel centers sit at half-integer coordinates
+        let x = u.rem_euclid(1.0) * width as f64 - 0.5;
+        let y = (1.0 - v.clamp(0.0, 1.0)) * height as f64 - 0.5;
+        let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+
+        let top = (1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+        let bottom = (1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
\ No newline at end of file
@@ -0,0 +1,83 @@
+use std::str::FromStr;
+
+use crate::vec3::Color;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(Operator::Clamp),
+            "reinhard" => Ok(Operator::Reinhard),
+            "reinhard-extended" | "extended-reinhard" => Ok(Operator::ExtendedReinhard),
+            "aces" => Ok(Operator::Aces),
+            _ => Err(format!("Unknown tone mapping operator '{}'", s)),
+        }
+    }
+}
+
+// Maps linear scene radiance into [0, 1] display-referred values, ready for encoding to 8-bit.
+#[derive(Debug, Copy, Clone)]
+pub struct ToneMap {
+    operator: Operator,
+    exposure: f64,
+    white_point: f64,
+}
+
+impl ToneMap {
+
+    // `exposure` is in stops; `white_point` is the smallest luminance mapped to pure white by
+    // the extended Reinhard operator.
+    pub fn new(operator: Operator, exposure: f64, white_point: f64) -> Self {
+        ToneMap {
+            operator,
+            exposure,
+            white_point,
+        }
+    }
+
+    // Leaves values as they are, apart from the clamp applied by 8-bit encoding
+    pub fn identity() -> Self {
+        ToneMap::new(Operator::Clamp, 0.0, 1.0)
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        let c = 2.0_f64.powf(self.exposure) * color;
+
+        match self.operator {
+            Operator::Clamp => c,
+            Operator::Reinhard => {
+                let l = c.luminance();
+                scale_luminance(c, l / (1.0 + l))
+            }
+            Operator::ExtendedReinhard => {
+                let l = c.luminance();
+                let lw2 = self.white_point * self.white_point;
+                scale_luminance(c, l * (1.0 + l / lw2) / (1.0 + l))
+            }
+            Operator::Aces => Color::new(aces(c.x()), aces(c.y()), aces(c.z())),
+        }
+    }
+}
+
+fn scale_luminance(c: Color, new_luminance: f64) -> Color {
+    let l = c.luminance();
+    if l <= 0.0 {
+        return Color::zero()
+    }
+    c * (new_luminance / l)
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic reference rendering transform
+fn aces(x: f64) -> f64 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    ((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0.0, 1.0)
+}
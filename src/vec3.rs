@@ -1,4 +1,4 @@
-use std::{ops::{Add, Sub, Mul, Div}, fmt::Display};
+use std::{ops::{Add, Sub, Mul, Div, Neg}, fmt::Display};
 use rand::{thread_rng, Rng};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -19,12 +19,18 @@ impl Vec3 {
     }
 
     pub fn random() -> Vec3 {
-        let mut rng = thread_rng();
+        Vec3::random_with(&mut thread_rng())
+    }
+
+    pub fn random_with<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         Vec3 {x: rng.gen(), y: rng.gen(), z: rng.gen()}
     }
 
     pub fn random_in_range<T1: Into<f64>, T2: Into<f64>>(min: T1, max: T2) -> Vec3 {
-        let mut rng = thread_rng();
+        Vec3::random_in_range_with(&mut thread_rng(), min, max)
+    }
+
+    pub fn random_in_range_with<R: Rng + ?Sized, T1: Into<f64>, T2: Into<f64>>(rng: &mut R, min: T1, max: T2) -> Vec3 {
         let min = min.into();
         let max = max.into();
         Vec3 {
@@ -325,6 +331,20 @@ impl Sub<Vec3> for f64 {
     }
 }
 
+// Negation of vec3
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Self::Output {
+        Vec3 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
 // Multiplication of f64 with vec3, and potential ref combinations TODO
 
 impl Mul<f64> for Vec3 {
@@ -401,21 +421,19 @@ pub type Point3 = Vec3;
 pub type Color = Vec3;
 
 impl Color {
-    pub fn to_rgba(&self, alpha: u8, samples_per_pixel: u64) -> [u8;4] {
-        
-        let mut r = self.x;
-        let mut g = self.y;
-        let mut b = self.z;
-
-        let scale = 1.0 / (samples_per_pixel as f64);
-        r   = (scale * r).sqrt();
-        g = (scale * g).sqrt();
-        b = (scale * b).sqrt();
-
-        let ir = (256.0 * r.clamp(0.0, 0.999)) as u8;
-        let ig = (256.0 * g.clamp(0.0, 0.999)) as u8;
-        let ib = (256.0 * b.clamp(0.0, 0.999)) as u8;
-
-        [ir, ig, ib, alpha]
+    // Encodes linear values in [0, 1] with the sRGB transfer function and quantizes to 8-bit
+    pub fn to_rgba(&self, alpha: u8) -> [u8;4] {
+        let encoded = self.to_srgb();
+        let quantize = |c: f64| (255.0 * c.clamp(0.0, 1.0)).round() as u8;
+
+        [quantize(encoded.x), quantize(encoded.y), quantize(encoded.z), alpha]
+    }
+
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
+    pub fn to_rgb_f32(&self) -> [f32;3] {
+        [self.x as f32, self.y as f32, self.z as f32]
     }
 }
\ No newline at end of file